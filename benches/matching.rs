@@ -0,0 +1,38 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::Deserialize;
+use structpath::{parse_path_generic, Schema};
+
+const SCHEMA: &str = "/users/<user_id:u64>/repos/<repo>/blob/<rev>/<path..>";
+const PATH: &str = "/users/1234/repos/structpath/blob/master/src/lib.rs";
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Blob<'a> {
+    user_id: u64,
+    repo: &'a str,
+    rev: &'a str,
+    path: &'a str,
+}
+
+fn matching(c: &mut Criterion) {
+    let schema = Schema::path(SCHEMA).unwrap();
+    let compiled = schema.compile();
+
+    let mut group = c.benchmark_group("matching");
+    group.bench_function("parse_path_generic", |b| {
+        b.iter(|| parse_path_generic(black_box(PATH).to_owned(), &schema).unwrap())
+    });
+    group.bench_function("parse_str", |b| {
+        b.iter(|| schema.parse_str::<Blob>(black_box(PATH)).unwrap())
+    });
+    group.bench_function("compiled_match_path", |b| {
+        b.iter(|| compiled.match_path(black_box(PATH)).unwrap().value(0))
+    });
+    group.bench_function("compiled_no_match", |b| {
+        b.iter(|| compiled.match_path(black_box("/users/1234/repos/structpath/tree/master")))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, matching);
+criterion_main!(benches);
@@ -0,0 +1,397 @@
+//! Matching paths against a `Schema` which has been prepared ahead of time.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use percent_encoding::percent_decode_str;
+
+use crate::pattern::split_pattern;
+use crate::{
+    decode_query_component, next_query_value, split_query, MatchMode, PatternPart, QueryParamSchema, Schema, SegmentSchema,
+    SegmentType, SegmentValue, SegmentValueSchema,
+};
+
+/// A single segment of a `CompiledSchema`
+#[derive(Debug)]
+enum CompiledSegment {
+    Literal(Box<[u8]>),
+    Value(usize, SegmentValueSchema),
+    Tail(usize),
+    /// The parts of the pattern, and the index of each of its values
+    Pattern(Vec<PatternPart>, Vec<usize>),
+}
+
+/// CompiledSchema is a `Schema` prepared for matching many paths
+///
+/// Compiling works out everything about the schema which doesn't depend on the path up front:
+/// the expected number of segments, the bytes of each literal, and an index for each captured
+/// value so no names need to be looked up or cloned while matching. Query parameters come after
+/// the path's values, in the order the schema lists them.
+///
+/// # Examples
+///
+/// ```
+/// use structpath::{Schema, SegmentValue};
+///
+/// let compiled = Schema::path("/users/<id:u64>/<name>").unwrap().compile();
+/// let captures = compiled.match_path("/users/12/ben").unwrap();
+/// assert_eq!(captures.value(0), Some(SegmentValue::U64(12)));
+/// assert_eq!(captures.get(compiled.index_of("name").unwrap()), Some("ben"));
+/// assert!(compiled.match_path("/users/ben/12").is_none());
+/// ```
+#[derive(Debug)]
+pub struct CompiledSchema {
+    schema: Schema,
+    names: Vec<String>,
+    segments: Vec<CompiledSegment>,
+    required: usize,
+}
+
+impl CompiledSchema {
+    pub(crate) fn new(schema: &Schema) -> Self {
+        let mut names = vec![];
+        let mut segments = vec![];
+        let mut required = 0;
+        for segment_schema in &schema.segments {
+            segments.push(match segment_schema {
+                SegmentSchema::Literal(literal) => {
+                    required += 1;
+                    CompiledSegment::Literal(literal.as_bytes().into())
+                },
+                SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema) => {
+                    if let SegmentSchema::Value(_) = segment_schema {
+                        required += 1;
+                    }
+                    names.push(segment_value_schema.name.clone());
+                    CompiledSegment::Value(names.len() - 1, segment_value_schema.clone())
+                },
+                SegmentSchema::Tail(name) => {
+                    names.push(name.clone());
+                    CompiledSegment::Tail(names.len() - 1)
+                },
+                SegmentSchema::Pattern(parts) => {
+                    required += 1;
+                    let mut indices = vec![];
+                    for segment_value_schema in pattern_values(parts) {
+                        names.push(segment_value_schema.name.clone());
+                        indices.push(names.len() - 1);
+                    }
+                    CompiledSegment::Pattern(parts.clone(), indices)
+                },
+            });
+        }
+        names.extend(schema.query.iter().map(|query_param_schema| query_param_schema.value_schema().name.clone()));
+        Self{schema: schema.clone(), names, segments, required}
+    }
+
+    /// The `Schema` this was compiled from
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The names of the captured values, in the order of their indices
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The index of the captured value called `name`
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|existing| existing == name)
+    }
+
+    fn accepts_count(&self, count: usize) -> bool {
+        let unbounded = self.schema.match_mode == MatchMode::Prefix
+            || matches!(self.segments.last(), Some(CompiledSegment::Tail(_)));
+        count >= self.required && (unbounded || count <= self.segments.len())
+    }
+
+    /// The query parameter at `index`, if it's one of them rather than a value in the path
+    fn query_param(&self, index: usize) -> Option<&QueryParamSchema> {
+        self.schema.query.get(index.checked_sub(self.names.len() - self.schema.query.len())?)
+    }
+
+    /// Match a path, returning the position of each captured value
+    ///
+    /// Returns `None` if the path doesn't match, including when a value can't be parsed as its
+    /// `SegmentType`. Query parameters are matched like `Schema::parse` does, so required ones
+    /// must be present and only repeated ones may appear more than once.
+    pub fn match_path<'c, 'p>(&'c self, full_path: &'p str) -> Option<Captures<'c, 'p>> {
+        let (path, query) = split_query(full_path);
+        if !self.accepts_count(path.matches('/').count()) {
+            return None;
+        }
+        let mut spans = vec![None; self.names.len()];
+        // offset of the start of the next segment, `None` once the path runs out
+        let mut start = path.find('/').map(|index| index + 1);
+        for segment in &self.segments {
+            let segment_start = match start {
+                Some(segment_start) => segment_start,
+                // only optional segments or a tail can be left over once the path runs out
+                None => break,
+            };
+            if let CompiledSegment::Tail(index) = segment {
+                if !is_valid_encoding(&path[segment_start..]) {
+                    return None;
+                }
+                spans[*index] = Some(segment_start..path.len());
+                break;
+            }
+            let segment_end = path[segment_start..].find('/').map_or(path.len(), |index| segment_start + index);
+            start = path.get(segment_end..).filter(|rest| !rest.is_empty()).map(|_| segment_end + 1);
+            let raw_segment = &path[segment_start..segment_end];
+            match segment {
+                CompiledSegment::Literal(literal) => {
+                    if raw_segment.as_bytes() != &literal[..] && !decoded_equals(raw_segment, literal) {
+                        return None;
+                    }
+                },
+                CompiledSegment::Value(index, segment_value_schema) => {
+                    let valid = match (&segment_value_schema.segment_type, &segment_value_schema.constraint) {
+                        (SegmentType::String | SegmentType::Enum, None) => is_valid_encoding(raw_segment),
+                        _ => percent_decode_str(raw_segment).decode_utf8().ok()
+                            .is_some_and(|segment| segment_value_schema.parse(&segment).is_ok()),
+                    };
+                    if !valid {
+                        return None;
+                    }
+                    spans[*index] = Some(segment_start..segment_end);
+                },
+                CompiledSegment::Pattern(parts, indices) => {
+                    let captures = split_pattern(parts, raw_segment).ok()?;
+                    for (index, (_, range)) in indices.iter().zip(captures) {
+                        spans[*index] = Some(segment_start + range.start..segment_start + range.end);
+                    }
+                },
+                CompiledSegment::Tail(_) => return None,
+            }
+        }
+        let first_query = self.names.len() - self.schema.query.len();
+        for (position, query_param_schema) in self.schema.query.iter().enumerate() {
+            let segment_value_schema = query_param_schema.value_schema();
+            let mut rest = query;
+            let mut raw_values = vec![];
+            while let Some(raw_value) = next_query_value(&mut rest, &segment_value_schema.name) {
+                let valid = decode_query_component(raw_value).ok()
+                    .is_some_and(|value| segment_value_schema.parse(&value).is_ok());
+                if !valid {
+                    return None;
+                }
+                raw_values.push(raw_value);
+            }
+            match (query_param_schema, raw_values.as_slice()) {
+                // repeated values are collected again by `Captures::value`
+                (QueryParamSchema::Repeated(_), _) | (QueryParamSchema::Optional(_), []) => (),
+                (_, [raw_value]) => spans[first_query + position] = Some(span_of(full_path, raw_value)),
+                _ => return None,
+            }
+        }
+        Some(Captures{compiled: self, path: full_path, spans})
+    }
+}
+
+/// The byte range of `part` within `text`, which it must be a slice of
+fn span_of(text: &str, part: &str) -> Range<usize> {
+    let start = part.as_ptr() as usize - text.as_ptr() as usize;
+    start..start + part.len()
+}
+
+/// The values of a pattern, in order
+fn pattern_values(parts: &[PatternPart]) -> impl Iterator<Item = &SegmentValueSchema> {
+    parts.iter().filter_map(|part| match part {
+        PatternPart::Value(segment_value_schema) => Some(segment_value_schema),
+        PatternPart::Literal(_) => None,
+    })
+}
+
+/// Whether raw text percent-decodes to valid UTF-8, only decoding if there is anything to decode
+fn is_valid_encoding(raw: &str) -> bool {
+    !raw.contains('%') || percent_decode_str(raw).decode_utf8().is_ok()
+}
+
+/// Whether a raw segment is the percent-encoded form of `literal`
+fn decoded_equals(raw_segment: &str, literal: &[u8]) -> bool {
+    raw_segment.contains('%') && percent_decode_str(raw_segment).eq(literal.iter().copied())
+}
+
+impl Schema {
+    /// Prepare this `Schema` for matching many paths, see `CompiledSchema`
+    pub fn compile(&self) -> CompiledSchema {
+        CompiledSchema::new(self)
+    }
+}
+
+/// Captures are the values of a path matched by a `CompiledSchema`, looked up by index
+///
+/// Indices are the order of the values in the schema, see `CompiledSchema::index_of`.
+/// Missing optional values, and tails where the path ends before the tail, are `None`.
+/// Repeated query parameters have no single raw text, so only `value` gives them, as a
+/// `SegmentValue::Sequence`.
+#[derive(Debug)]
+pub struct Captures<'c, 'p> {
+    compiled: &'c CompiledSchema,
+    path: &'p str,
+    spans: Vec<Option<Range<usize>>>,
+}
+
+impl<'c, 'p> Captures<'c, 'p> {
+    /// The number of values in the schema, whether or not they were captured
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Whether the schema has no values
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// The name of the value at `index`
+    pub fn name(&self, index: usize) -> Option<&'c str> {
+        self.compiled.names.get(index).map(String::as_str)
+    }
+
+    /// The raw text of the value at `index`, before percent-decoding
+    pub fn get(&self, index: usize) -> Option<&'p str> {
+        let span = self.spans.get(index)?.clone()?;
+        Some(&self.path[span])
+    }
+
+    /// The percent-decoded text of the value at `index`, only allocated if decoding changed anything
+    pub fn decoded(&self, index: usize) -> Option<Cow<'p, str>> {
+        let raw = self.get(index)?;
+        if self.compiled.query_param(index).is_some() {
+            return decode_query_component(raw).ok();
+        }
+        // matching already checked the decoded value is valid UTF-8
+        Some(percent_decode_str(raw).decode_utf8_lossy())
+    }
+
+    /// The value at `index` parsed as its `SegmentType`
+    ///
+    /// Tails are always a `SegmentValue::Sequence`, which is empty if the path ends before the tail.
+    pub fn value(&self, index: usize) -> Option<SegmentValue> {
+        match self.compiled.query_param(index) {
+            Some(QueryParamSchema::Repeated(segment_value_schema)) => {
+                let mut rest = split_query(self.path).1;
+                let mut values = vec![];
+                while let Some(raw_value) = next_query_value(&mut rest, &segment_value_schema.name) {
+                    values.push(segment_value_schema.parse(&decode_query_component(raw_value).ok()?).ok()?);
+                }
+                return Some(SegmentValue::Sequence(values));
+            },
+            Some(query_param_schema) => return query_param_schema.value_schema().parse(&self.decoded(index)?).ok(),
+            None => (),
+        }
+        // the schema of the value, or `None` for a tail
+        let segment_value_schema = self.compiled.segments.iter().find_map(|segment| match segment {
+            CompiledSegment::Value(value_index, segment_value_schema) if *value_index == index => Some(Some(segment_value_schema)),
+            CompiledSegment::Pattern(parts, indices) => {
+                let position = indices.iter().position(|value_index| *value_index == index)?;
+                pattern_values(parts).nth(position).map(Some)
+            },
+            CompiledSegment::Tail(value_index) if *value_index == index => Some(None),
+            _ => None,
+        })?;
+        match segment_value_schema {
+            Some(segment_value_schema) => segment_value_schema.parse(&self.decoded(index)?).ok(),
+            None => Some(SegmentValue::Sequence(
+                self.get(index).into_iter()
+                    .flat_map(|raw| raw.split('/'))
+                    .map(|raw_segment| SegmentValue::String(percent_decode_str(raw_segment).decode_utf8_lossy().into_owned()))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_path_generic;
+
+    #[test]
+    fn test_match_path() {
+        let compiled = Schema::path("/users/<id:u64>/posts/<page:u32?>").unwrap().compile();
+        assert_eq!(compiled.names(), ["id".to_owned(), "page".to_owned()]);
+        let captures = compiled.match_path("/users/12/posts/3").unwrap();
+        assert_eq!(captures.len(), 2);
+        assert_eq!(captures.name(1), Some("page"));
+        assert_eq!(captures.value(0), Some(SegmentValue::U64(12)));
+        assert_eq!(captures.value(1), Some(SegmentValue::U32(3)));
+        let captures = compiled.match_path("/users/12/posts").unwrap();
+        assert_eq!(captures.get(1), None);
+        assert!(compiled.match_path("/users/12").is_none());
+        assert!(compiled.match_path("/users/12/posts/3/4").is_none());
+        assert!(compiled.match_path("/users/x/posts").is_none());
+        assert!(compiled.match_path("/user/12/posts").is_none());
+    }
+
+    #[test]
+    fn test_match_path_encoded() {
+        let compiled = Schema::path("/a b/<name>").unwrap().compile();
+        let captures = compiled.match_path("/a%20b/c%2Fd").unwrap();
+        assert_eq!(captures.get(0), Some("c%2Fd"));
+        assert_eq!(captures.decoded(0), Some(Cow::Owned("c/d".to_owned())));
+        assert!(matches!(compiled.match_path("/a%20b/cd").unwrap().decoded(0), Some(Cow::Borrowed("cd"))));
+        assert!(compiled.match_path("/a%20b/%FF").is_none());
+    }
+
+    #[test]
+    fn test_match_path_tail_and_prefix() {
+        let compiled = Schema::path("/static/<path..>").unwrap().compile();
+        let captures = compiled.match_path("/static/css/main.css").unwrap();
+        assert_eq!(captures.get(0), Some("css/main.css"));
+        assert_eq!(compiled.match_path("/static").unwrap().get(0), None);
+
+        let compiled = Schema::path("/api").unwrap().match_mode(MatchMode::Prefix).compile();
+        assert!(compiled.match_path("/api/v1").is_some());
+        assert!(compiled.match_path("/other/v1").is_none());
+    }
+
+    #[test]
+    fn test_matches_parse_path_generic() {
+        let schema = Schema::path("/repo/<name>/<id:i32>/<rest..>").unwrap();
+        let compiled = schema.compile();
+        for path in ["/repo/x/-1", "/repo/x/1/a/b", "/repo/x%20y/2/", "/repo/x/y", "/repo/x", "/other/x/1"] {
+            let generic = parse_path_generic(path.to_owned(), &schema).ok();
+            let captures = compiled.match_path(path).map(|captures| {
+                (0..captures.len())
+                    .filter_map(|index| Some((captures.name(index)?.to_owned(), captures.value(index)?)))
+                    .collect()
+            });
+            assert_eq!(captures, generic, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_match_path_query() {
+        let schema = Schema::path("/search?<q>&<page:u32?>&<tag:u8..>").unwrap();
+        let compiled = schema.compile();
+        assert_eq!(compiled.names(), ["q".to_owned(), "page".to_owned(), "tag".to_owned()]);
+        let captures = compiled.match_path("/search?tag=1&q=a+b%21&tag=2").unwrap();
+        assert_eq!(captures.get(0), Some("a+b%21"));
+        assert_eq!(captures.decoded(0), Some(Cow::Owned("a b!".to_owned())));
+        assert_eq!(captures.value(1), None);
+        assert_eq!(captures.get(2), None);
+        assert_eq!(captures.value(2), Some(SegmentValue::Sequence(vec![SegmentValue::U8(1), SegmentValue::U8(2)])));
+        assert_eq!(compiled.match_path("/search?q=x&page=3").unwrap().value(1), Some(SegmentValue::U32(3)));
+        assert!(compiled.match_path("/search").is_none());
+        assert!(compiled.match_path("/search?q=x&q=y").is_none());
+        assert!(compiled.match_path("/search?q=x&page=y").is_none());
+        assert!(compiled.match_path("/search?q=x&tag=300").is_none());
+        for path in ["/search", "/search?q=x", "/search?q=x&tag=1&page=2&other=3", "/search?q=x&page=1&page=2"] {
+            assert_eq!(compiled.match_path(path).is_some(), schema.parse_generic(path).is_ok(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_match_path_pattern() {
+        let compiled = Schema::path("/img/<w:u32>x<h:u32>.<format>").unwrap().compile();
+        assert_eq!(compiled.names(), ["w".to_owned(), "h".to_owned(), "format".to_owned()]);
+        let captures = compiled.match_path("/img/640x480.png").unwrap();
+        assert_eq!(captures.value(0), Some(SegmentValue::U32(640)));
+        assert_eq!(captures.value(1), Some(SegmentValue::U32(480)));
+        assert_eq!(captures.get(2), Some("png"));
+        assert!(compiled.match_path("/img/640x.png").is_none());
+        assert!(compiled.match_path("/img/640.png").is_none());
+    }
+}
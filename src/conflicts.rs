@@ -0,0 +1,580 @@
+//! Detecting schemas which can match the same paths.
+
+use std::fmt;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::pattern::split_pattern;
+use crate::router::value_priority;
+use crate::{parse_segment_value, MatchMode, PatternPart, Schema, SegmentSchema, SegmentType, SegmentValueSchema};
+
+/// ConflictKind is the kind of problem between two schemas
+///
+/// `Duplicate` means both schemas match exactly the same paths in the same way (only their value
+/// names may differ), so the loser can never match.
+///
+/// `Shadowed` means every path the loser matches is also matched by the winner, and the winner
+/// always takes priority, so the loser can never match.
+///
+/// `Overlap` means some paths are matched by both schemas, the winner takes priority for those
+/// paths, but the loser still matches others.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ConflictKind {
+    Duplicate,
+    Shadowed,
+    Overlap,
+}
+
+/// SegmentConflict is a pair of segments, at the same position, which both match some path segment
+#[derive(PartialEq, Debug, Clone)]
+pub struct SegmentConflict {
+    /// Index of the segment in the path (ignoring the leading `/`)
+    pub position: usize,
+    /// The segment from the winning schema
+    pub winner: SegmentSchema,
+    /// The segment from the losing schema
+    pub loser: SegmentSchema,
+}
+
+/// Conflict describes two schemas which can match the same path
+///
+/// `winner` and `loser` are indices into the schemas passed to `find_conflicts`, the winner is the
+/// schema a `Router` would pick for paths matched by both.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub winner: usize,
+    pub loser: usize,
+    /// The segments where the two schemas differ but still overlap
+    pub segments: Vec<SegmentConflict>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ConflictKind::Duplicate => write!(f, "schema {} is a duplicate of schema {}", self.loser, self.winner)?,
+            ConflictKind::Shadowed => write!(f, "schema {} is shadowed by schema {}", self.loser, self.winner)?,
+            ConflictKind::Overlap => write!(f, "schema {} overlaps schema {} (schema {} wins)", self.loser, self.winner, self.winner)?,
+        }
+        for segment in &self.segments {
+            write!(
+                f,
+                "; segment {}: {} matches before {}",
+                segment.position,
+                segment.winner,
+                segment.loser,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How the set of path segments matched by one segment schema relates to another's
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Relation {
+    Equal,
+    Narrower,
+    Wider,
+    Overlap,
+    Disjoint,
+}
+
+impl Relation {
+    fn combine(self, other: Relation) -> Relation {
+        match (self, other) {
+            (Relation::Disjoint, _) | (_, Relation::Disjoint) => Relation::Disjoint,
+            (Relation::Equal, relation) | (relation, Relation::Equal) => relation,
+            (Relation::Narrower, Relation::Narrower) => Relation::Narrower,
+            (Relation::Wider, Relation::Wider) => Relation::Wider,
+            _ => Relation::Overlap,
+        }
+    }
+}
+
+/// Signedness and width of an integer `SegmentType`
+fn integer_bits(segment_type: &SegmentType) -> Option<(bool, u32)> {
+    match segment_type {
+        SegmentType::U8 => Some((false, 8)),
+        SegmentType::U16 => Some((false, 16)),
+        SegmentType::U32 => Some((false, 32)),
+        SegmentType::U64 => Some((false, 64)),
+        SegmentType::U128 => Some((false, 128)),
+        SegmentType::I8 => Some((true, 8)),
+        SegmentType::I16 => Some((true, 16)),
+        SegmentType::I32 => Some((true, 32)),
+        SegmentType::I64 => Some((true, 64)),
+        SegmentType::I128 => Some((true, 128)),
+        _ => None,
+    }
+}
+
+/// Every segment accepted by a `SegmentType` which only accepts a few
+fn finite_values(segment_type: &SegmentType) -> Option<Vec<&str>> {
+    match segment_type {
+        SegmentType::Choice(choices) => Some(choices.iter().map(String::as_str).collect()),
+        SegmentType::Bool(bool_format) => Some(vec![bool_format.true_text(), bool_format.false_text()]),
+        _ => None,
+    }
+}
+
+/// Whether every segment accepted by `narrow` is also accepted by `wide`
+fn accepts_all(wide: &SegmentType, narrow: &SegmentType) -> bool {
+    if let Some(values) = finite_values(narrow) {
+        return values.iter().all(|value| parse_segment_value(value, wide).is_ok());
+    }
+    match (wide, narrow) {
+        (SegmentType::String | SegmentType::Enum, _) => true,
+        (_, SegmentType::String | SegmentType::Enum) => false,
+        (SegmentType::Choice(_) | SegmentType::Bool(_), _) => false,
+        (SegmentType::Char, SegmentType::Char) => true,
+        (SegmentType::Custom(wide), SegmentType::Custom(narrow)) => wide == narrow,
+        // nothing is known about which segments a custom type accepts
+        (SegmentType::Custom(_), _) | (_, SegmentType::Custom(_)) => false,
+        // numbers can have more than one digit, and most characters aren't numbers
+        (SegmentType::Char, _) | (_, SegmentType::Char) => false,
+        // floats accept any number, out of range values just become infinite
+        (SegmentType::F32, _) | (SegmentType::F64, _) => true,
+        (_, SegmentType::F32) | (_, SegmentType::F64) => false,
+        _ => match (integer_bits(wide), integer_bits(narrow)) {
+            (Some((wide_signed, wide_bits)), Some((narrow_signed, narrow_bits))) => {
+                if wide_signed == narrow_signed {
+                    narrow_bits <= wide_bits
+                } else {
+                    wide_signed && narrow_bits < wide_bits
+                }
+            },
+            _ => false,
+        },
+    }
+}
+
+fn segment_relation(a: &SegmentSchema, b: &SegmentSchema) -> Relation {
+    match (a, b) {
+        (SegmentSchema::Literal(a_literal), SegmentSchema::Literal(b_literal)) => {
+            if a_literal == b_literal {
+                Relation::Equal
+            } else {
+                Relation::Disjoint
+            }
+        },
+        (SegmentSchema::Literal(literal), SegmentSchema::Value(segment_value_schema))
+        | (SegmentSchema::Literal(literal), SegmentSchema::Optional(segment_value_schema)) => {
+            match segment_value_schema.parse(literal) {
+                Ok(_) => Relation::Narrower,
+                Err(_) => Relation::Disjoint,
+            }
+        },
+        (SegmentSchema::Literal(literal), SegmentSchema::Pattern(parts)) => {
+            match split_pattern(parts, &utf8_percent_encode(literal, NON_ALPHANUMERIC).to_string()) {
+                Ok(_) => Relation::Narrower,
+                Err(_) => Relation::Disjoint,
+            }
+        },
+        (SegmentSchema::Pattern(parts), SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema)) => {
+            match (&segment_value_schema.segment_type, &segment_value_schema.constraint) {
+                (SegmentType::String | SegmentType::Enum, None) => Relation::Narrower,
+                // any segment matching a pattern contains its literal text, which may not be a valid integer
+                _ if integer_bits(&segment_value_schema.segment_type).is_some() && parts.iter().any(|part| match part {
+                    PatternPart::Literal(literal) => literal.chars().any(|c| !c.is_ascii_digit() && c != '+' && c != '-'),
+                    PatternPart::Value(_) => false,
+                }) => Relation::Disjoint,
+                _ => Relation::Overlap,
+            }
+        },
+        (SegmentSchema::Pattern(a_parts), SegmentSchema::Pattern(b_parts)) => pattern_relation(a_parts, b_parts),
+        (SegmentSchema::Value(_) | SegmentSchema::Optional(_), SegmentSchema::Literal(_))
+        | (SegmentSchema::Pattern(_), SegmentSchema::Literal(_))
+        | (SegmentSchema::Value(_) | SegmentSchema::Optional(_), SegmentSchema::Pattern(_)) => match segment_relation(b, a) {
+            Relation::Narrower => Relation::Wider,
+            Relation::Wider => Relation::Narrower,
+            relation => relation,
+        },
+        (SegmentSchema::Value(a_value) | SegmentSchema::Optional(a_value), SegmentSchema::Value(b_value) | SegmentSchema::Optional(b_value)) => {
+            constrained_relation(value_relation(a_value, b_value), a_value, b_value)
+        },
+        // tails are handled by `Variant::rest`
+        (SegmentSchema::Tail(_), _) | (_, SegmentSchema::Tail(_)) => Relation::Overlap,
+    }
+}
+
+/// Relate two value segments by their types alone
+fn value_relation(a_value: &SegmentValueSchema, b_value: &SegmentValueSchema) -> Relation {
+    match (accepts_all(&a_value.segment_type, &b_value.segment_type), accepts_all(&b_value.segment_type, &a_value.segment_type)) {
+        (true, true) => Relation::Equal,
+        (true, false) => Relation::Wider,
+        (false, true) => Relation::Narrower,
+        (false, false) => {
+            let (a_type, b_type) = (&a_value.segment_type, &b_value.segment_type);
+            let overlaps = match (finite_values(a_type), finite_values(b_type)) {
+                (Some(values), _) => values.iter().any(|value| parse_segment_value(value, b_type).is_ok()),
+                (None, Some(values)) => values.iter().any(|value| parse_segment_value(value, a_type).is_ok()),
+                // every pair of numeric (or char) types accepts "0", and custom types
+                // are assumed to overlap anything
+                (None, None) => true,
+            };
+            if overlaps {
+                Relation::Overlap
+            } else {
+                Relation::Disjoint
+            }
+        },
+    }
+}
+
+/// The literal text a pattern starts and ends with, empty if it starts or ends with a value
+fn pattern_bounds(parts: &[PatternPart]) -> [&str; 2] {
+    fn literal(part: Option<&PatternPart>) -> &str {
+        match part {
+            Some(PatternPart::Literal(literal)) => literal,
+            _ => "",
+        }
+    }
+    [literal(parts.first()), literal(parts.last())]
+}
+
+/// Relate two patterns, which are only compared by their shape and the text they start and end with
+fn pattern_relation(a_parts: &[PatternPart], b_parts: &[PatternPart]) -> Relation {
+    let [a_start, a_end] = pattern_bounds(a_parts);
+    let [b_start, b_end] = pattern_bounds(b_parts);
+    if !(a_start.starts_with(b_start) || b_start.starts_with(a_start)) || !(a_end.ends_with(b_end) || b_end.ends_with(a_end)) {
+        Relation::Disjoint
+    } else if same_pattern(a_parts, b_parts) {
+        Relation::Equal
+    } else {
+        Relation::Overlap
+    }
+}
+
+/// Whether two patterns are the same apart from the names of their values
+fn same_pattern(a_parts: &[PatternPart], b_parts: &[PatternPart]) -> bool {
+    a_parts.len() == b_parts.len() && a_parts.iter().zip(b_parts).all(|parts| match parts {
+        (PatternPart::Literal(a_literal), PatternPart::Literal(b_literal)) => a_literal == b_literal,
+        (PatternPart::Value(a_value), PatternPart::Value(b_value)) => {
+            a_value.segment_type == b_value.segment_type && a_value.constraint == b_value.constraint
+        },
+        _ => false,
+    })
+}
+
+/// Adjust the relation between two value segments' types for their constraints
+///
+/// Constraints are opaque, so a constrained value is only known to be narrower than an
+/// unconstrained one, and two different constraints are assumed to overlap.
+fn constrained_relation(relation: Relation, a_value: &SegmentValueSchema, b_value: &SegmentValueSchema) -> Relation {
+    match (&a_value.constraint, &b_value.constraint, relation) {
+        (_, _, Relation::Disjoint) => Relation::Disjoint,
+        (None, None, relation) => relation,
+        (Some(a_constraint), Some(b_constraint), relation) if a_constraint == b_constraint => relation,
+        (Some(_), None, Relation::Equal | Relation::Narrower) => Relation::Narrower,
+        (None, Some(_), Relation::Equal | Relation::Wider) => Relation::Wider,
+        _ => Relation::Overlap,
+    }
+}
+
+/// Whatever matches the remaining segments of a path once the fixed segments run out
+#[derive(Debug, Clone, Copy)]
+enum Rest<'s> {
+    Tail(&'s SegmentSchema),
+    Prefix,
+}
+
+/// One of the fixed length forms a `Schema` can take once its optional segments are expanded
+#[derive(Debug, Clone)]
+struct Variant<'s> {
+    fixed: Vec<&'s SegmentSchema>,
+    rest: Option<Rest<'s>>,
+}
+
+fn variants(schema: &Schema) -> Vec<Variant<'_>> {
+    let prefix = match schema.match_mode {
+        MatchMode::Strict => None,
+        MatchMode::Prefix => Some(Rest::Prefix),
+    };
+    let mut variants = vec![];
+    let mut fixed = vec![];
+    for segment_schema in &schema.segments {
+        match segment_schema {
+            SegmentSchema::Optional(_) => {
+                variants.push(Variant{fixed: fixed.clone(), rest: prefix});
+                fixed.push(segment_schema);
+            },
+            SegmentSchema::Tail(_) => {
+                variants.push(Variant{fixed, rest: Some(Rest::Tail(segment_schema))});
+                return variants;
+            },
+            _ => fixed.push(segment_schema),
+        }
+    }
+    variants.push(Variant{fixed, rest: prefix});
+    variants
+}
+
+/// Relate the paths matched by two variants, along with the segments where they differ
+fn variant_relation<'s>(a: &Variant<'s>, b: &Variant<'s>) -> (Relation, Vec<(usize, &'s SegmentSchema, &'s SegmentSchema)>) {
+    let common = a.fixed.len().min(b.fixed.len());
+    let mut relation = match (a.rest, b.rest) {
+        (None, None) if a.fixed.len() != b.fixed.len() => return (Relation::Disjoint, vec![]),
+        (Some(_), None) if b.fixed.len() < a.fixed.len() => return (Relation::Disjoint, vec![]),
+        (None, Some(_)) if a.fixed.len() < b.fixed.len() => return (Relation::Disjoint, vec![]),
+        _ if a.fixed.len() < b.fixed.len() => Relation::Wider,
+        _ if a.fixed.len() > b.fixed.len() => Relation::Narrower,
+        _ => Relation::Equal,
+    };
+    let mut differences = vec![];
+    for position in 0..common {
+        let segment_relation = segment_relation(a.fixed[position], b.fixed[position]);
+        if segment_relation != Relation::Equal || segment_priority(a.fixed[position]) != segment_priority(b.fixed[position]) {
+            differences.push((position, a.fixed[position], b.fixed[position]));
+        }
+        relation = relation.combine(segment_relation);
+    }
+    match (a.rest, b.rest) {
+        (Some(Rest::Tail(tail)), _) if common < b.fixed.len() => differences.push((common, tail, b.fixed[common])),
+        (_, Some(Rest::Tail(tail))) if common < a.fixed.len() => differences.push((common, a.fixed[common], tail)),
+        _ => (),
+    }
+    if relation == Relation::Disjoint {
+        differences.clear();
+    }
+    (relation, differences)
+}
+
+fn segment_priority(segment_schema: &SegmentSchema) -> usize {
+    match segment_schema {
+        SegmentSchema::Literal(_) => 0,
+        SegmentSchema::Pattern(_) => 1,
+        SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema) => {
+            2 + value_priority(segment_value_schema)
+        },
+        SegmentSchema::Tail(_) => usize::MAX,
+    }
+}
+
+fn rest_priority(rest: Option<Rest>) -> usize {
+    match rest {
+        None => 0,
+        Some(Rest::Tail(_)) => 1,
+        Some(Rest::Prefix) => 2,
+    }
+}
+
+/// Whether `a` (registered at index `a_index`) is picked over `b` for paths they both match,
+/// following the same rules as `Router`
+fn beats(a: &Variant, a_index: usize, b: &Variant, b_index: usize) -> bool {
+    for (a_segment, b_segment) in a.fixed.iter().zip(b.fixed.iter()) {
+        let (a_priority, b_priority) = (segment_priority(a_segment), segment_priority(b_segment));
+        if a_priority != b_priority {
+            return a_priority < b_priority;
+        }
+    }
+    if a.fixed.len() != b.fixed.len() {
+        // the shorter variant only continues with its rest, which is tried after any segment
+        return a.fixed.len() > b.fixed.len();
+    }
+    let (a_priority, b_priority) = (rest_priority(a.rest), rest_priority(b.rest));
+    if a_priority != b_priority {
+        return a_priority < b_priority;
+    }
+    a_index < b_index
+}
+
+/// Whether two schemas match exactly the same paths in the same way
+fn is_duplicate(a: &Schema, b: &Schema) -> bool {
+    a.match_mode == b.match_mode
+        && a.segments.len() == b.segments.len()
+        && a.segments.iter().zip(b.segments.iter()).all(|(a_segment, b_segment)| match (a_segment, b_segment) {
+            (SegmentSchema::Literal(a_literal), SegmentSchema::Literal(b_literal)) => a_literal == b_literal,
+            (SegmentSchema::Value(a_value), SegmentSchema::Value(b_value))
+            | (SegmentSchema::Optional(a_value), SegmentSchema::Optional(b_value)) => {
+                a_value.segment_type == b_value.segment_type && a_value.constraint == b_value.constraint
+            },
+            (SegmentSchema::Pattern(a_parts), SegmentSchema::Pattern(b_parts)) => same_pattern(a_parts, b_parts),
+            (SegmentSchema::Tail(_), SegmentSchema::Tail(_)) => true,
+            _ => false,
+        })
+}
+
+fn segment_conflicts(differences: Vec<(usize, &SegmentSchema, &SegmentSchema)>) -> Vec<SegmentConflict> {
+    differences.into_iter()
+        .map(|(position, winner, loser)| SegmentConflict{position, winner: winner.clone(), loser: loser.clone()})
+        .collect()
+}
+
+/// If every variant of the loser is matched by, and loses to, some variant of the winner, returns
+/// the differing segments
+fn shadowing(
+    winner: &[Variant],
+    winner_index: usize,
+    loser: &[Variant],
+    loser_index: usize,
+) -> Option<Vec<SegmentConflict>> {
+    let mut conflicts = vec![];
+    for loser_variant in loser {
+        let (_, differences) = winner.iter()
+            .map(|winner_variant| (winner_variant, variant_relation(winner_variant, loser_variant)))
+            .find(|(winner_variant, (relation, _))| {
+                (*relation == Relation::Equal || *relation == Relation::Wider)
+                    && beats(winner_variant, winner_index, loser_variant, loser_index)
+            })?;
+        for conflict in segment_conflicts(differences.1) {
+            if !conflicts.contains(&conflict) {
+                conflicts.push(conflict);
+            }
+        }
+    }
+    Some(conflicts)
+}
+
+/// Find every pair of schemas which can match the same path
+///
+/// Each `Conflict` explains which schema a `Router` would pick when both match, and whether the
+/// other schema can still match anything. Only pairs of schemas are considered, a schema which is
+/// only shadowed by a combination of several others is reported as overlapping each of them.
+///
+/// # Examples
+///
+/// ```
+/// use structpath::{find_conflicts, ConflictKind, Schema};
+///
+/// let conflicts = find_conflicts(&[
+///     Schema::path("/users/<id:u64>").unwrap(),
+///     Schema::path("/users/<id:u8>").unwrap(),
+///     Schema::path("/users/<name>").unwrap(),
+/// ]);
+/// assert_eq!(conflicts[0].kind, ConflictKind::Overlap);
+/// assert_eq!(conflicts.len(), 3);
+/// ```
+pub fn find_conflicts<'s, I>(schemas: I) -> Vec<Conflict> where I: IntoIterator<Item = &'s Schema> {
+    let schemas: Vec<&Schema> = schemas.into_iter().collect();
+    let all_variants: Vec<Vec<Variant>> = schemas.iter().map(|schema| variants(schema)).collect();
+    let mut conflicts = vec![];
+    for (first, first_schema) in schemas.iter().enumerate() {
+        for (second, second_schema) in schemas.iter().enumerate().skip(first + 1) {
+            if is_duplicate(first_schema, second_schema) {
+                conflicts.push(Conflict{kind: ConflictKind::Duplicate, winner: first, loser: second, segments: vec![]});
+                continue;
+            }
+            let (first_variants, second_variants) = (&all_variants[first], &all_variants[second]);
+            if let Some(segments) = shadowing(first_variants, first, second_variants, second) {
+                conflicts.push(Conflict{kind: ConflictKind::Shadowed, winner: first, loser: second, segments});
+                continue;
+            }
+            if let Some(segments) = shadowing(second_variants, second, first_variants, first) {
+                conflicts.push(Conflict{kind: ConflictKind::Shadowed, winner: second, loser: first, segments});
+                continue;
+            }
+            let overlap = first_variants.iter()
+                .flat_map(|first_variant| second_variants.iter().map(move |second_variant| (first_variant, second_variant)))
+                .map(|(first_variant, second_variant)| (first_variant, second_variant, variant_relation(first_variant, second_variant)))
+                .find(|(_, _, (relation, _))| *relation != Relation::Disjoint);
+            if let Some((first_variant, second_variant, (_, differences))) = overlap {
+                let mut segments = segment_conflicts(differences);
+                let (winner, loser) = if beats(first_variant, first, second_variant, second) {
+                    (first, second)
+                } else {
+                    for segment in &mut segments {
+                        std::mem::swap(&mut segment.winner, &mut segment.loser);
+                    }
+                    (second, first)
+                };
+                conflicts.push(Conflict{kind: ConflictKind::Overlap, winner, loser, segments});
+            }
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflicts(paths: &[&str]) -> Vec<(ConflictKind, usize, usize)> {
+        let schemas: Vec<Schema> = paths.iter().map(|path| Schema::path(*path).unwrap()).collect();
+        find_conflicts(&schemas).into_iter().map(|conflict| (conflict.kind, conflict.winner, conflict.loser)).collect()
+    }
+
+    #[test]
+    fn test_no_conflicts() {
+        assert_eq!(conflicts(&["/users/<id:u64>", "/posts/<id:u64>", "/users/<id:u64>/posts", "/users/-"]), vec![]);
+    }
+
+    #[test]
+    fn test_duplicate() {
+        assert_eq!(conflicts(&["/users/<id:u64>", "/users/<user_id:u64>"]), vec![(ConflictKind::Duplicate, 0, 1)]);
+    }
+
+    #[test]
+    fn test_overlap() {
+        assert_eq!(
+            conflicts(&["/users/<name>", "/users/<id:u64>", "/users/me"]),
+            vec![
+                (ConflictKind::Overlap, 1, 0),
+                (ConflictKind::Overlap, 2, 0),
+            ],
+            );
+        assert_eq!(conflicts(&["/users/<id:i64>", "/users/<id:u64>"]), vec![(ConflictKind::Overlap, 1, 0)]);
+    }
+
+    #[test]
+    fn test_shadowed() {
+        assert_eq!(conflicts(&["/users/<id:u8>", "/users/<id:u64>"]), vec![(ConflictKind::Overlap, 0, 1)]);
+        assert_eq!(conflicts(&["/users/<id:f32>", "/users/<id:f64>"]), vec![(ConflictKind::Shadowed, 0, 1)]);
+        assert_eq!(conflicts(&["/users/<id:f64>", "/users/<id:u8>"]), vec![(ConflictKind::Overlap, 1, 0)]);
+        assert_eq!(conflicts(&["/static/<path..>", "/static/<a>/<b>"]), vec![(ConflictKind::Overlap, 1, 0)]);
+        assert_eq!(conflicts(&["/items/<page:u64?>", "/items"]), vec![(ConflictKind::Shadowed, 0, 1)]);
+        assert_eq!(conflicts(&["/items", "/items/<page:u64?>"]), vec![(ConflictKind::Overlap, 0, 1)]);
+    }
+
+    #[test]
+    fn test_choices() {
+        assert_eq!(conflicts(&["/issues/<status:open|closed>", "/issues/<id:u64>", "/issues/<sort:new|old>"]), vec![]);
+        assert_eq!(conflicts(&["/issues/<status:enum>", "/issues/<status:open|closed>"]), vec![(ConflictKind::Overlap, 1, 0)]);
+        assert_eq!(conflicts(&["/issues/<a:open|closed>", "/issues/<b:open|new>"]), vec![(ConflictKind::Overlap, 0, 1)]);
+        assert_eq!(conflicts(&["/issues/<a:1|2>", "/issues/<id:u8>"]), vec![(ConflictKind::Overlap, 0, 1)]);
+        assert_eq!(conflicts(&["/issues/<a:bool>", "/issues/<id:u8>", "/issues/<c:char>"]), vec![(ConflictKind::Overlap, 1, 2)]);
+        assert_eq!(conflicts(&["/issues/<a:bool(1|0)>", "/issues/<id:u8>"]), vec![(ConflictKind::Overlap, 0, 1)]);
+    }
+
+    #[test]
+    fn test_constraints() {
+        assert_eq!(conflicts(&["/posts/<id:u64>", "/posts/<id:u64(1..=10)>"]), vec![(ConflictKind::Overlap, 1, 0)]);
+        assert_eq!(conflicts(&["/posts/<a:String(^a)>", "/posts/<b:String(b$)>"]), vec![(ConflictKind::Overlap, 0, 1)]);
+        assert_eq!(conflicts(&["/posts/<a:u8(..5)>", "/posts/<b:u8(..5)>"]), vec![(ConflictKind::Duplicate, 0, 1)]);
+        assert_eq!(conflicts(&["/posts/<id:u8(1..5)>", "/posts/new"]), vec![]);
+    }
+
+    #[test]
+    fn test_patterns() {
+        // patterns are tried in registration order
+        assert_eq!(conflicts(&["/files/<name>.<ext>", "/files/<id:u64>", "/files/v<version:u32>.json"]), vec![
+            (ConflictKind::Overlap, 0, 2),
+        ]);
+        assert_eq!(conflicts(&["/files/<name>", "/files/<name>.<ext>"]), vec![(ConflictKind::Overlap, 1, 0)]);
+        assert_eq!(conflicts(&["/files/<a>.<b>", "/files/<c>.<d>"]), vec![(ConflictKind::Duplicate, 0, 1)]);
+        assert_eq!(conflicts(&["/files/<name>.txt", "/files/<name>.png", "/files/readme"]), vec![]);
+        assert_eq!(conflicts(&["/files/<name>.txt", "/files/notes.txt"]), vec![(ConflictKind::Overlap, 1, 0)]);
+    }
+
+    #[test]
+    fn test_explanation() {
+        let schemas = [Schema::path("/users/<id:f32>").unwrap(), Schema::path("/users/<id:f64>").unwrap()];
+        let conflicts = find_conflicts(&schemas);
+        assert_eq!(conflicts[0].segments, vec![SegmentConflict{
+            position: 1,
+            winner: SegmentSchema::Value(crate::SegmentValueSchema{name: "id".to_owned(), segment_type: SegmentType::F32, constraint: None}),
+            loser: SegmentSchema::Value(crate::SegmentValueSchema{name: "id".to_owned(), segment_type: SegmentType::F64, constraint: None}),
+        }]);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "schema 1 is shadowed by schema 0; segment 1: <id:f32> matches before <id:f64>",
+            );
+    }
+
+    #[test]
+    fn test_router_conflicts() {
+        let router = crate::Router::new()
+            .route(Schema::path("/users/<id:u64>").unwrap(), ())
+            .route(Schema::path("/users/<id:u64>").unwrap(), ());
+        assert_eq!(router.conflicts()[0].kind, ConflictKind::Duplicate);
+    }
+}
@@ -0,0 +1,239 @@
+//! Constraints restricting which values a value segment accepts.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::{parse_segment_value, SegmentType, SegmentTypeRegistry, SegmentValue};
+
+/// Constraint restricts the values accepted by a value segment, beyond its `SegmentType`
+///
+/// `Regex` must match the percent-decoded segment, e.g. `<slug:String(^[a-z0-9-]+$)>`
+///
+/// `Range` bounds a numeric value, e.g. `<page:u32(1..=1000)>`, the bounds have the same
+/// `SegmentValue` variant as the segment's type
+///
+/// `Predicate` is a named function which must return `true` for the percent-decoded segment, e.g.
+/// `<code:String(@three_letters)>`, see `register_predicate` and `Schema::value_with`
+#[derive(Clone)]
+pub enum Constraint {
+    Regex(Regex),
+    Range(Bound<SegmentValue>, Bound<SegmentValue>),
+    Predicate(String, Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl Constraint {
+    /// A `Constraint::Regex` from a regular expression
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Constraint::Regex(Regex::new(pattern)?))
+    }
+
+    /// A `Constraint::Predicate` from a function, called `name` in path schemas
+    pub fn predicate<S, F>(name: S, predicate: F) -> Self where S: Into<String>, F: Fn(&str) -> bool + Send + Sync + 'static {
+        Constraint::Predicate(name.into(), Arc::new(predicate))
+    }
+
+    /// Parse the constraint in a path schema, e.g. `1..=1000` from `<page:u32(1..=1000)>`
+    ///
+    /// `@` followed by a name is a predicate looked up in `registry`, otherwise numeric types take
+    /// a range and anything else takes a regular expression, which is written with an extra `@`
+    /// if it starts with one.
+    pub(crate) fn parse(text: &str, segment_type: &SegmentType, registry: &SegmentTypeRegistry) -> Result<Self, String> {
+        let text = match text.strip_prefix('@') {
+            Some(name) if !name.starts_with('@') => {
+                return registry.predicate(name).cloned().ok_or_else(|| format!("Unrecognized predicate {:?}", name));
+            },
+            Some(text) => text,
+            None => text,
+        };
+        if !is_numeric(segment_type) {
+            return Constraint::regex(text).map_err(|error| error.to_string());
+        }
+        let (start, end) = text.split_once("..").ok_or_else(|| format!("Expected a range, got {:?}", text))?;
+        let bound = |text: &str| parse_segment_value(text, segment_type).map_err(|error| error.to_string());
+        let start = match start {
+            "" => Bound::Unbounded,
+            start => Bound::Included(bound(start)?),
+        };
+        let end = match end.strip_prefix('=') {
+            Some(end) => Bound::Included(bound(end)?),
+            None if end.is_empty() => Bound::Unbounded,
+            None => Bound::Excluded(bound(end)?),
+        };
+        Ok(Constraint::Range(start, end))
+    }
+
+    /// Whether a percent-decoded segment, and the value parsed from it, satisfy this constraint
+    pub fn check(&self, segment: &str, value: &SegmentValue) -> bool {
+        match self {
+            Constraint::Regex(regex) => regex.is_match(segment),
+            Constraint::Range(start, end) => {
+                let above_start = match start {
+                    Bound::Included(start) => matches!(compare(value, start), Some(Ordering::Greater | Ordering::Equal)),
+                    Bound::Excluded(start) => matches!(compare(value, start), Some(Ordering::Greater)),
+                    Bound::Unbounded => true,
+                };
+                let below_end = match end {
+                    Bound::Included(end) => matches!(compare(value, end), Some(Ordering::Less | Ordering::Equal)),
+                    Bound::Excluded(end) => matches!(compare(value, end), Some(Ordering::Less)),
+                    Bound::Unbounded => true,
+                };
+                above_start && below_end
+            },
+            Constraint::Predicate(_, predicate) => predicate(segment),
+        }
+    }
+}
+
+fn is_numeric(segment_type: &SegmentType) -> bool {
+    matches!(
+        segment_type,
+        SegmentType::F32 | SegmentType::F64
+            | SegmentType::I8 | SegmentType::I16 | SegmentType::I32 | SegmentType::I64 | SegmentType::I128
+            | SegmentType::U8 | SegmentType::U16 | SegmentType::U32 | SegmentType::U64 | SegmentType::U128
+    )
+}
+
+/// Compare two numeric values of the same type
+fn compare(a: &SegmentValue, b: &SegmentValue) -> Option<Ordering> {
+    match (a, b) {
+        (SegmentValue::F32(a), SegmentValue::F32(b)) => a.partial_cmp(b),
+        (SegmentValue::F64(a), SegmentValue::F64(b)) => a.partial_cmp(b),
+        (SegmentValue::I8(a), SegmentValue::I8(b)) => a.partial_cmp(b),
+        (SegmentValue::I16(a), SegmentValue::I16(b)) => a.partial_cmp(b),
+        (SegmentValue::I32(a), SegmentValue::I32(b)) => a.partial_cmp(b),
+        (SegmentValue::I64(a), SegmentValue::I64(b)) => a.partial_cmp(b),
+        (SegmentValue::I128(a), SegmentValue::I128(b)) => a.partial_cmp(b),
+        (SegmentValue::U8(a), SegmentValue::U8(b)) => a.partial_cmp(b),
+        (SegmentValue::U16(a), SegmentValue::U16(b)) => a.partial_cmp(b),
+        (SegmentValue::U32(a), SegmentValue::U32(b)) => a.partial_cmp(b),
+        (SegmentValue::U64(a), SegmentValue::U64(b)) => a.partial_cmp(b),
+        (SegmentValue::U128(a), SegmentValue::U128(b)) => a.partial_cmp(b),
+        _ => None,
+    }
+}
+
+/// Write a range bound's value the way it's written in a path schema
+fn write_bound(f: &mut fmt::Formatter, value: &SegmentValue) -> fmt::Result {
+    match value {
+        SegmentValue::F32(value) => write!(f, "{}", value),
+        SegmentValue::F64(value) => write!(f, "{}", value),
+        SegmentValue::I8(value) => write!(f, "{}", value),
+        SegmentValue::I16(value) => write!(f, "{}", value),
+        SegmentValue::I32(value) => write!(f, "{}", value),
+        SegmentValue::I64(value) => write!(f, "{}", value),
+        SegmentValue::I128(value) => write!(f, "{}", value),
+        SegmentValue::U8(value) => write!(f, "{}", value),
+        SegmentValue::U16(value) => write!(f, "{}", value),
+        SegmentValue::U32(value) => write!(f, "{}", value),
+        SegmentValue::U64(value) => write!(f, "{}", value),
+        SegmentValue::U128(value) => write!(f, "{}", value),
+        value => write!(f, "{:?}", value),
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constraint::Regex(regex) if regex.as_str().starts_with('@') => write!(f, "@{}", regex.as_str()),
+            Constraint::Regex(regex) => write!(f, "{}", regex.as_str()),
+            Constraint::Range(start, end) => {
+                if let Bound::Included(start) | Bound::Excluded(start) = start {
+                    write_bound(f, start)?;
+                }
+                match end {
+                    Bound::Included(end) => {
+                        write!(f, "..=")?;
+                        write_bound(f, end)
+                    },
+                    Bound::Excluded(end) => {
+                        write!(f, "..")?;
+                        write_bound(f, end)
+                    },
+                    Bound::Unbounded => write!(f, ".."),
+                }
+            },
+            Constraint::Predicate(name, _) => write!(f, "@{}", name),
+        }
+    }
+}
+
+impl fmt::Debug for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constraint::Regex(regex) => f.debug_tuple("Regex").field(&regex.as_str()).finish(),
+            Constraint::Range(start, end) => f.debug_tuple("Range").field(start).field(end).finish(),
+            Constraint::Predicate(name, _) => f.debug_tuple("Predicate").field(name).finish(),
+        }
+    }
+}
+
+/// Regexes are equal if their patterns are, and predicates if they have the same name
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Constraint::Regex(a), Constraint::Regex(b)) => a.as_str() == b.as_str(),
+            (Constraint::Range(a_start, a_end), Constraint::Range(b_start, b_end)) => a_start == b_start && a_end == b_end,
+            (Constraint::Predicate(a, _), Constraint::Predicate(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str, segment_type: &SegmentType) -> Result<Constraint, String> {
+        let registry = SegmentTypeRegistry::new().register_predicate("short", |segment| segment.len() < 4);
+        Constraint::parse(text, segment_type, &registry)
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let constraint = parse("1..=1000", &SegmentType::U32).unwrap();
+        assert_eq!(constraint, Constraint::Range(Bound::Included(SegmentValue::U32(1)), Bound::Included(SegmentValue::U32(1000))));
+        assert_eq!(constraint.to_string(), "1..=1000");
+        assert!(constraint.check("1", &SegmentValue::U32(1)));
+        assert!(constraint.check("1000", &SegmentValue::U32(1000)));
+        assert!(!constraint.check("0", &SegmentValue::U32(0)));
+        assert!(!constraint.check("1001", &SegmentValue::U32(1001)));
+
+        let constraint = parse("-1.5..", &SegmentType::F64).unwrap();
+        assert_eq!(constraint.to_string(), "-1.5..");
+        assert!(constraint.check("3", &SegmentValue::F64(3.0)));
+        assert!(!constraint.check("-2", &SegmentValue::F64(-2.0)));
+
+        let constraint = parse("..10", &SegmentType::I8).unwrap();
+        assert!(constraint.check("9", &SegmentValue::I8(9)));
+        assert!(!constraint.check("10", &SegmentValue::I8(10)));
+
+        assert!(parse("1", &SegmentType::U8).is_err());
+        assert!(parse("1..x", &SegmentType::U8).is_err());
+    }
+
+    #[test]
+    fn test_regex_and_predicate() {
+        let constraint = parse("^[a-z-]+$", &SegmentType::String).unwrap();
+        assert_eq!(constraint, Constraint::regex("^[a-z-]+$").unwrap());
+        assert!(constraint.check("hello-world", &SegmentValue::String("hello-world".to_owned())));
+        assert!(!constraint.check("Hello", &SegmentValue::String("Hello".to_owned())));
+        assert!(parse("[", &SegmentType::String).is_err());
+
+        let constraint = parse("@short", &SegmentType::U32).unwrap();
+        assert!(constraint.check("123", &SegmentValue::U32(123)));
+        assert!(!constraint.check("1234", &SegmentValue::U32(1234)));
+        assert_eq!(constraint.to_string(), "@short");
+        assert_eq!(constraint, Constraint::predicate("short", |_| true));
+        assert_ne!(constraint, Constraint::predicate("long", |segment| segment.len() < 4));
+        assert_eq!(parse("@long", &SegmentType::String).unwrap_err(), "Unrecognized predicate \"long\"");
+
+        // a regular expression starting with `@` is written with an extra one
+        let constraint = parse("@@[a-z]+", &SegmentType::String).unwrap();
+        assert_eq!(constraint, Constraint::regex("@[a-z]+").unwrap());
+        assert_eq!(constraint.to_string(), "@@[a-z]+");
+    }
+}
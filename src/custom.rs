@@ -0,0 +1,208 @@
+//! Custom segment types, registered by name for use in path schemas.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::Constraint;
+
+/// SegmentParser checks segments of a custom `SegmentType`
+///
+/// # Examples
+///
+/// ```
+/// use structpath::{register_segment_type, Schema, SegmentParser};
+///
+/// #[derive(Debug)]
+/// struct HexHash;
+///
+/// impl SegmentParser for HexHash {
+///     fn name(&self) -> &str {
+///         "hex"
+///     }
+///
+///     fn validate(&self, segment: &str) -> Result<(), String> {
+///         if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_hexdigit()) {
+///             Ok(())
+///         } else {
+///             Err("expected hex digits".to_owned())
+///         }
+///     }
+/// }
+///
+/// register_segment_type(HexHash);
+/// let schema = Schema::path("/commits/<hash:hex>").unwrap();
+/// ```
+pub trait SegmentParser: fmt::Debug + Send + Sync {
+    /// The name of this type in path schemas, e.g. `uuid` for `<id:uuid>`
+    fn name(&self) -> &str;
+
+    /// Check a percent-decoded segment is a valid value of this type
+    fn validate(&self, segment: &str) -> Result<(), String>;
+}
+
+/// CustomType is a `SegmentParser` shared between `SegmentType`s
+///
+/// Two `CustomType`s are equal if their parsers have the same name.
+#[derive(Clone)]
+pub struct CustomType(Arc<dyn SegmentParser>);
+
+impl CustomType {
+    /// Wrap a `SegmentParser`
+    pub fn new<P: SegmentParser + 'static>(parser: P) -> Self {
+        Self(Arc::new(parser))
+    }
+
+    /// The name of this type in path schemas
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    /// Check a percent-decoded segment is a valid value of this type
+    pub fn validate(&self, segment: &str) -> Result<(), String> {
+        self.0.validate(segment)
+    }
+}
+
+impl PartialEq for CustomType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl fmt::Debug for CustomType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CustomType").field(&self.name()).finish()
+    }
+}
+
+/// FromStrParser is a `SegmentParser` accepting any segment `T` can be parsed from
+///
+/// e.g. `FromStrParser::<Uuid>::new("uuid")`, fields of type `T` can then be parsed and generated
+/// with `#[serde(with = "structpath::display_fromstr")]`.
+pub struct FromStrParser<T> {
+    name: String,
+    parsed: PhantomData<fn() -> T>,
+}
+
+impl<T> FromStrParser<T> {
+    /// Create a `FromStrParser` called `name` in path schemas
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self{name: name.into(), parsed: PhantomData}
+    }
+}
+
+impl<T> fmt::Debug for FromStrParser<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FromStrParser").field("name", &self.name).finish()
+    }
+}
+
+impl<T> SegmentParser for FromStrParser<T> where T: FromStr, T::Err: fmt::Display {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self, segment: &str) -> Result<(), String> {
+        T::from_str(segment).map(|_| ()).map_err(|error| error.to_string())
+    }
+}
+
+/// SegmentTypeRegistry maps type names in path schemas to custom `SegmentParser`s, and predicate
+/// names to `Constraint::Predicate`s
+///
+/// `Schema::path` uses a global registry, see `register_segment_type` and `register_predicate`, while
+/// `Schema::path_with_registry` can be given a registry of its own.
+#[derive(Debug, Default, Clone)]
+pub struct SegmentTypeRegistry {
+    types: HashMap<String, CustomType>,
+    predicates: HashMap<String, Constraint>,
+}
+
+impl SegmentTypeRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self{types: HashMap::new(), predicates: HashMap::new()}
+    }
+
+    /// Add a custom type, replacing any other type with the same name
+    pub fn register<P: SegmentParser + 'static>(mut self, parser: P) -> Self {
+        self.insert(CustomType::new(parser));
+        self
+    }
+
+    fn insert(&mut self, custom_type: CustomType) {
+        self.types.insert(custom_type.name().to_owned(), custom_type);
+    }
+
+    /// The custom type called `name`, if there is one
+    pub fn get(&self, name: &str) -> Option<&CustomType> {
+        self.types.get(name)
+    }
+
+    /// Add a predicate for `<name:type(@predicate)>` constraints, replacing any other predicate
+    /// with the same name
+    pub fn register_predicate<S, F>(mut self, name: S, predicate: F) -> Self where S: Into<String>, F: Fn(&str) -> bool + Send + Sync + 'static {
+        self.insert_predicate(Constraint::predicate(name, predicate));
+        self
+    }
+
+    fn insert_predicate(&mut self, predicate: Constraint) {
+        if let Constraint::Predicate(name, _) = &predicate {
+            self.predicates.insert(name.clone(), predicate);
+        }
+    }
+
+    /// The `Constraint::Predicate` called `name`, if there is one
+    pub fn predicate(&self, name: &str) -> Option<&Constraint> {
+        self.predicates.get(name)
+    }
+}
+
+fn global_registry() -> &'static RwLock<SegmentTypeRegistry> {
+    static REGISTRY: OnceLock<RwLock<SegmentTypeRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(SegmentTypeRegistry::new()))
+}
+
+/// Add a custom type to the global registry used by `Schema::path`
+///
+/// Types should be registered before any schema using them is created.
+pub fn register_segment_type<P: SegmentParser + 'static>(parser: P) {
+    global_registry().write().unwrap_or_else(|error| error.into_inner()).insert(CustomType::new(parser));
+}
+
+/// Add a predicate to the global registry used by `Schema::path`, see `Constraint::Predicate`
+///
+/// Like types, predicates should be registered before any schema using them is created.
+pub fn register_predicate<S, F>(name: S, predicate: F) where S: Into<String>, F: Fn(&str) -> bool + Send + Sync + 'static {
+    global_registry().write().unwrap_or_else(|error| error.into_inner()).insert_predicate(Constraint::predicate(name, predicate));
+}
+
+/// Run `f` with the global registry
+pub(crate) fn with_global_registry<T>(f: impl FnOnce(&SegmentTypeRegistry) -> T) -> T {
+    f(&global_registry().read().unwrap_or_else(|error| error.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = SegmentTypeRegistry::new()
+            .register(FromStrParser::<u8>::new("byte"))
+            .register(FromStrParser::<std::net::Ipv4Addr>::new("ip"));
+        let ip = registry.get("ip").unwrap();
+        assert_eq!(ip.name(), "ip");
+        assert!(ip.validate("127.0.0.1").is_ok());
+        assert!(ip.validate("localhost").is_err());
+        assert_eq!(registry.get("byte"), Some(&CustomType::new(FromStrParser::<u16>::new("byte"))));
+        assert!(registry.get("uuid").is_none());
+
+        let registry = registry.register_predicate("even", |segment| segment.ends_with(['0', '2', '4', '6', '8']));
+        assert!(registry.predicate("even").unwrap().check("12", &crate::SegmentValue::U8(12)));
+        assert!(registry.predicate("odd").is_none());
+    }
+}
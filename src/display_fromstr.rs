@@ -0,0 +1,42 @@
+//! serde helpers for fields written with `Display` and parsed with `FromStr`.
+//!
+//! Use with `#[serde(with = "structpath::display_fromstr")]`, typically on fields parsed from a
+//! custom segment type (see `FromStrParser`).
+//!
+//! # Examples
+//!
+//! ```
+//! use std::net::Ipv4Addr;
+//! use serde::{Deserialize, Serialize};
+//! use structpath::{FromStrParser, Schema, SegmentType};
+//!
+//! #[derive(Deserialize, Serialize, PartialEq, Debug)]
+//! struct HostParams {
+//!     #[serde(with = "structpath::display_fromstr")]
+//!     ip: Ipv4Addr,
+//! }
+//!
+//! let schema = Schema::new()
+//!     .literal("hosts")
+//!     .value("ip", SegmentType::custom(FromStrParser::<Ipv4Addr>::new("ip")));
+//! let params: HostParams = schema.parse("/hosts/127.0.0.1").unwrap();
+//! assert_eq!(params, HostParams{ip: Ipv4Addr::LOCALHOST});
+//! assert_eq!(schema.generate(&params).unwrap(), "/hosts/127.0.0.1");
+//! ```
+
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Serialize a value as its `Display` text
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error> where T: Display, S: Serializer {
+    serializer.collect_str(value)
+}
+
+/// Deserialize a value by parsing text with `FromStr`
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error> where T: FromStr, T::Err: Display, D: Deserializer<'de> {
+    let text = <Cow<str>>::deserialize(deserializer)?;
+    text.parse().map_err(serde::de::Error::custom)
+}
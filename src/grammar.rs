@@ -0,0 +1,456 @@
+//! Parsing path schemas, e.g. `/users/<id:u64>/posts/<page:u32?>?<sort?>`.
+//!
+//! Outside of values, `\` escapes the next character, so `\<`, `\>`, `\?`, `\/` and `\\` can be
+//! used in literal text. Inside values, `\<`, `\>` and `\/` stand for those characters and any
+//! other `\` is kept as is, so constraints like `<id:String(^\d+$)>` keep their meaning.
+//!
+//! A final `/<..>` segment sets `MatchMode::Prefix` rather than adding a segment.
+
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
+
+use crate::{
+    BoolFormat, Constraint, MatchMode, PathSchemaParseError, PatternPart, QueryParamSchema, Schema,
+    SegmentSchema, SegmentType, SegmentTypeRegistry, SegmentValueSchema,
+};
+
+/// A piece of a path schema, along with the byte range it was written at
+#[derive(Debug)]
+enum Token {
+    /// Literal text, with any escapes resolved
+    Text(String, Range<usize>),
+    /// The inside of a value, with any escapes resolved, the range includes the brackets
+    Value(String, Range<usize>),
+    Slash(usize),
+    Query(usize),
+    Ampersand(usize),
+}
+
+impl Token {
+    fn span(&self) -> Range<usize> {
+        match self {
+            Token::Text(_, span) | Token::Value(_, span) => span.clone(),
+            Token::Slash(index) | Token::Query(index) | Token::Ampersand(index) => *index..*index + 1,
+        }
+    }
+}
+
+/// Parse a path schema, looking up custom segment types in `registry`
+pub(crate) fn parse_schema(path: &str, registry: &SegmentTypeRegistry) -> Result<Schema, PathSchemaParseError> {
+    Parser{path, registry, names: HashSet::new()}.parse()
+}
+
+/// Parse a type as it's written after the `:` in a value, e.g. `u64` or `bool(yes|no)`
+pub(crate) fn parse_segment_type(text: &str, registry: &SegmentTypeRegistry) -> Result<SegmentType, PathSchemaParseError> {
+    Parser{path: text, registry, names: HashSet::new()}.segment_type(text, &(0..text.len()))
+}
+
+/// Check a `Schema` put together from its parts follows the same rules as a parsed path schema
+pub(crate) fn check_schema(schema: &Schema) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let mut check_name = |name: &str| {
+        if !is_value_name(name) {
+            Err(format!("Expected the name {:?} to be an identifier", name))
+        } else if let Some(other) = clashing_name(&names, name) {
+            Err(format!("Value name {:?} clashes with {:?}", name, other))
+        } else if !names.insert(name.to_owned()) {
+            Err(format!("Value name {:?} is used more than once", name))
+        } else {
+            Ok(())
+        }
+    };
+    let mut previous = None;
+    for segment in &schema.segments {
+        check_segment(previous, segment)?;
+        match segment {
+            SegmentSchema::Literal(_) => (),
+            SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema) => {
+                check_name(&segment_value_schema.name)?;
+            },
+            SegmentSchema::Tail(name) => check_name(name)?,
+            SegmentSchema::Pattern(parts) => {
+                for part in parts {
+                    if let PatternPart::Value(segment_value_schema) = part {
+                        check_name(&segment_value_schema.name)?;
+                    }
+                }
+            },
+        }
+        previous = Some(segment);
+    }
+    for query_param in &schema.query {
+        check_name(&query_param.value_schema().name)?;
+        check_segment_type(&query_param.value_schema().segment_type)?;
+    }
+    Ok(())
+}
+
+/// Check a segment may follow the one before it, that a pattern can be written and that the types
+/// of its values can be written
+pub(crate) fn check_segment(previous: Option<&SegmentSchema>, segment: &SegmentSchema) -> Result<(), String> {
+    check_order(previous, segment)?;
+    match segment {
+        SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema) => {
+            check_segment_type(&segment_value_schema.segment_type)
+        },
+        SegmentSchema::Pattern(parts) => {
+            if !parts.iter().any(|part| matches!(part, PatternPart::Value(_))) {
+                return Err("A pattern must have at least one value".to_owned());
+            }
+            if parts.windows(2).any(|pair| matches!(pair, [PatternPart::Value(_), PatternPart::Value(_)])) {
+                return Err("Values in a segment must be separated by literal text".to_owned());
+            }
+            let empty = |part: &PatternPart| matches!(part, PatternPart::Literal(literal) if literal.is_empty());
+            if parts.iter().any(empty) || parts.windows(2).any(|pair| matches!(pair, [PatternPart::Literal(_), PatternPart::Literal(_)])) {
+                return Err("Literal text in a segment must be a single non-empty part".to_owned());
+            }
+            if !parts.iter().any(|part| matches!(part, PatternPart::Literal(_))) {
+                return Err("A pattern must have literal text, a lone value is a value segment".to_owned());
+            }
+            parts.iter().try_for_each(|part| match part {
+                PatternPart::Value(segment_value_schema) => check_segment_type(&segment_value_schema.segment_type),
+                PatternPart::Literal(_) => Ok(()),
+            })
+        },
+        SegmentSchema::Literal(_) | SegmentSchema::Tail(_) => Ok(()),
+    }
+}
+
+/// Check a type can be written in a path schema, which every type but some `Choice`s can
+pub(crate) fn check_segment_type(segment_type: &SegmentType) -> Result<(), String> {
+    if let SegmentType::Choice(choices) = segment_type {
+        if choices.len() < 2 {
+            return Err(format!("A choice must have at least two options, got {:?}", choices));
+        }
+        // these would be read as the end of the choice, a constraint or an escape
+        if let Some(choice) = choices.iter().find(|choice| choice.contains(['|', '(', ')', '?', '\\']) || choice.contains("..")) {
+            return Err(format!("The choice {:?} can't contain `|`, `(`, `)`, `?`, `\\` or `..`", choice));
+        }
+    }
+    Ok(())
+}
+
+/// Check a segment may follow the one before it
+pub(crate) fn check_order(previous: Option<&SegmentSchema>, segment: &SegmentSchema) -> Result<(), &'static str> {
+    match (previous, segment) {
+        (Some(SegmentSchema::Tail(_)), _) => Err("Tail segment must be the last segment"),
+        (Some(SegmentSchema::Optional(_)), SegmentSchema::Optional(_)) => Ok(()),
+        (Some(SegmentSchema::Optional(_)), _) => Err("Only optional segments may follow an optional segment"),
+        _ => Ok(()),
+    }
+}
+
+/// Whether a value name is an identifier, or identifiers joined by `.` for nested structs, e.g. `org.id`
+fn is_value_name(name: &str) -> bool {
+    name.split('.').all(is_identifier)
+}
+
+/// Find a name in `names` that `name` can't be used alongside because one contains the other,
+/// e.g. `org` and `org.id`
+fn clashing_name<'n>(names: &'n HashSet<String>, name: &str) -> Option<&'n str> {
+    let contains = |outer: &str, inner: &str| inner.strip_prefix(outer).is_some_and(|rest| rest.starts_with('.'));
+    names.iter().map(String::as_str).filter(|other| contains(other, name) || contains(name, other)).min()
+}
+
+/// Whether a name is an identifier, starting with a letter or `_`
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+struct Parser<'p> {
+    path: &'p str,
+    registry: &'p SegmentTypeRegistry,
+    /// Every value name seen so far, to catch duplicates
+    names: HashSet<String>,
+}
+
+impl<'p> Parser<'p> {
+    fn error<M: Into<String>>(&self, span: Range<usize>, message: M) -> PathSchemaParseError {
+        PathSchemaParseError::SyntaxError{segment: self.path[span.clone()].to_owned(), message: message.into(), span}
+    }
+
+    fn parse(mut self) -> Result<Schema, PathSchemaParseError> {
+        let tokens = self.tokenize()?;
+        let (path_tokens, query_tokens, path_end) = match tokens.iter().position(|token| matches!(token, Token::Query(_))) {
+            Some(position) => (&tokens[..position], Some(&tokens[position + 1..]), tokens[position].span().start),
+            None => (&tokens[..], None, self.path.len()),
+        };
+        let mut schema = Schema::new();
+        match path_tokens.first() {
+            None | Some(Token::Slash(_)) => (),
+            Some(token) => return Err(self.error(token.span(), "Path schema must start with '/'")),
+        }
+        let mut slashes = path_tokens.iter().enumerate().filter(|(_, token)| matches!(token, Token::Slash(_))).peekable();
+        while let Some((position, slash)) = slashes.next() {
+            let (end_position, end) = match slashes.peek() {
+                Some((end_position, next_slash)) => (*end_position, next_slash.span().start),
+                None => (path_tokens.len(), path_end),
+            };
+            let span = slash.span().end..end;
+            let tokens = &path_tokens[position + 1..end_position];
+            if matches!(tokens, [Token::Value(text, _)] if text == "..") {
+                if slashes.peek().is_some() {
+                    return Err(self.error(span, "<..> must be the last segment"));
+                }
+                if let Some(SegmentSchema::Tail(_)) = schema.segments.last() {
+                    return Err(self.error(span, "Tail segment must be the last segment"));
+                }
+                schema.match_mode = MatchMode::Prefix;
+                continue;
+            }
+            let segment_schema = self.segment(tokens, span.clone())?;
+            check_order(schema.segments.last(), &segment_schema).map_err(|message| self.error(span, message))?;
+            schema.segments.push(segment_schema);
+        }
+        for parameter in query_tokens.iter().flat_map(|tokens| tokens.split(|token| matches!(token, Token::Ampersand(_)))) {
+            schema.query.push(self.query_param(parameter, path_end..self.path.len())?);
+        }
+        Ok(schema)
+    }
+
+    /// Split the whole path schema into tokens
+    fn tokenize(&self) -> Result<Vec<Token>, PathSchemaParseError> {
+        let mut tokens = vec![];
+        let mut chars = self.path.char_indices().peekable();
+        let mut in_query = false;
+        while let Some((start, c)) = chars.next() {
+            match c {
+                '/' if !in_query => tokens.push(Token::Slash(start)),
+                '?' if !in_query => {
+                    in_query = true;
+                    tokens.push(Token::Query(start));
+                },
+                '&' if in_query => tokens.push(Token::Ampersand(start)),
+                '<' => tokens.push(self.value(start, &mut chars)?),
+                '>' => return Err(self.error(start..start + 1, "Unexpected '>', write \\> for a literal '>'")),
+                c => {
+                    let c = match (c, chars.next_if(|_| c == '\\')) {
+                        ('\\', Some((_, escaped))) => escaped,
+                        ('\\', None) => return Err(self.error(start..start + 1, "Expected a character to escape after '\\'")),
+                        (c, _) => c,
+                    };
+                    let end = chars.peek().map_or(self.path.len(), |(index, _)| *index);
+                    match tokens.last_mut() {
+                        Some(Token::Text(text, span)) if span.end == start => {
+                            text.push(c);
+                            span.end = end;
+                        },
+                        _ => tokens.push(Token::Text(c.to_string(), start..end)),
+                    }
+                },
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Read the rest of a value, after the `<` at `start`
+    fn value(&self, start: usize, chars: &mut Peekable<CharIndices>) -> Result<Token, PathSchemaParseError> {
+        let mut text = String::new();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '>' => return Ok(Token::Value(text, start..index + 1)),
+                '<' | '/' => {
+                    return Err(self.error(index..index + 1, format!("Unexpected '{}' inside a value, write \\{} for a literal '{}'", c, c, c)));
+                },
+                '\\' => match chars.next_if(|(_, escaped)| matches!(escaped, '<' | '>' | '/')) {
+                    Some((_, escaped)) => text.push(escaped),
+                    None => text.push('\\'),
+                },
+                c => text.push(c),
+            }
+        }
+        Err(self.error(start..self.path.len(), "Expected '>' to close value"))
+    }
+
+    /// Build the schema for the tokens between two slashes
+    fn segment(&mut self, tokens: &[Token], span: Range<usize>) -> Result<SegmentSchema, PathSchemaParseError> {
+        Ok(match tokens {
+            [] => SegmentSchema::Literal(String::new()),
+            [Token::Text(text, _)] => SegmentSchema::Literal(text.clone()),
+            [Token::Value(text, span)] => {
+                if let Some(name) = text.strip_suffix("..") {
+                    SegmentSchema::Tail(self.name(name, span)?)
+                } else if let Some(text) = text.strip_suffix('?') {
+                    SegmentSchema::Optional(self.value_schema(text, span)?)
+                } else {
+                    SegmentSchema::Value(self.value_schema(text, span)?)
+                }
+            },
+            tokens => {
+                let mut parts = vec![];
+                for token in tokens {
+                    parts.push(match token {
+                        Token::Text(text, _) => PatternPart::Literal(text.clone()),
+                        Token::Value(text, span) if text.ends_with("..") || text.ends_with('?') => {
+                            return Err(self.error(span.clone(), "Tail and optional values must take up a whole segment"));
+                        },
+                        Token::Value(_, span) if matches!(parts.last(), Some(PatternPart::Value(_))) => {
+                            return Err(self.error(span.clone(), "Values in a segment must be separated by literal text"));
+                        },
+                        Token::Value(text, span) => PatternPart::Value(self.value_schema(text, span)?),
+                        _ => return Err(self.error(span, "Unexpected token in path segment")),
+                    });
+                }
+                SegmentSchema::Pattern(parts)
+            },
+        })
+    }
+
+    /// Build the schema for the tokens between two `&`s in the query
+    fn query_param(&mut self, tokens: &[Token], query_span: Range<usize>) -> Result<QueryParamSchema, PathSchemaParseError> {
+        let (text, span) = match tokens {
+            [Token::Value(text, span)] => (text, span),
+            _ => {
+                let span = match (tokens.first(), tokens.last()) {
+                    (Some(first), Some(last)) => first.span().start..last.span().end,
+                    // point at the whole query for an empty parameter
+                    _ => query_span,
+                };
+                return Err(self.error(span, "Query parameters must be written as <name>"));
+            },
+        };
+        Ok(if let Some(text) = text.strip_suffix("..") {
+            QueryParamSchema::Repeated(self.value_schema(text, span)?)
+        } else if let Some(text) = text.strip_suffix('?') {
+            QueryParamSchema::Optional(self.value_schema(text, span)?)
+        } else {
+            QueryParamSchema::Required(self.value_schema(text, span)?)
+        })
+    }
+
+    /// Check a value's name is an identifier which hasn't been used before
+    fn name(&mut self, name: &str, span: &Range<usize>) -> Result<String, PathSchemaParseError> {
+        if !is_value_name(name) {
+            return Err(self.error(span.clone(), format!("Expected the name {:?} to be an identifier", name)));
+        }
+        if let Some(other) = clashing_name(&self.names, name) {
+            return Err(self.error(span.clone(), format!("Value name {:?} clashes with {:?}", name, other)));
+        }
+        if !self.names.insert(name.to_owned()) {
+            return Err(PathSchemaParseError::DuplicateName{name: name.to_owned(), span: span.clone()});
+        }
+        Ok(name.to_owned())
+    }
+
+    /// Parse the inside of a value, e.g. `foo:u64` from `<foo:u64>`
+    fn value_schema(&mut self, text: &str, span: &Range<usize>) -> Result<SegmentValueSchema, PathSchemaParseError> {
+        let (name, type_text) = text.split_once(':').unwrap_or((text, "String"));
+        let name = self.name(name, span)?;
+        // a constraint follows the type in brackets, e.g. `u32(1..=1000)`, except for `bool(yes|no)`
+        let (type_text, constraint_text) = match type_text.split_once('(') {
+            Some((type_text, rest)) if type_text != "bool" && rest.ends_with(')') => (type_text, Some(&rest[..rest.len() - 1])),
+            _ => (type_text, None),
+        };
+        let segment_type = self.segment_type(type_text, span)?;
+        let constraint = match constraint_text {
+            Some(constraint_text) => {
+                Some(Constraint::parse(constraint_text, &segment_type, self.registry).map_err(|message| self.error(span.clone(), message))?)
+            },
+            None => None,
+        };
+        Ok(SegmentValueSchema{name, segment_type, constraint})
+    }
+
+    fn segment_type(&self, type_text: &str, span: &Range<usize>) -> Result<SegmentType, PathSchemaParseError> {
+        Ok(match type_text {
+            "f32" => SegmentType::F32,
+            "f64" => SegmentType::F64,
+            "u8" => SegmentType::U8,
+            "u16" => SegmentType::U16,
+            "u32" => SegmentType::U32,
+            "u64" => SegmentType::U64,
+            "u128" => SegmentType::U128,
+            "i8" => SegmentType::I8,
+            "i16" => SegmentType::I16,
+            "i32" => SegmentType::I32,
+            "i64" => SegmentType::I64,
+            "i128" => SegmentType::I128,
+            "String" => SegmentType::String,
+            "enum" => SegmentType::Enum,
+            "bool" => SegmentType::Bool(BoolFormat::default()),
+            "char" => SegmentType::Char,
+            bool_format if bool_format.starts_with("bool(") && bool_format.ends_with(')') => {
+                match bool_format["bool(".len()..bool_format.len() - 1].split_once('|') {
+                    Some((true_text, false_text)) => SegmentType::Bool(BoolFormat::new(true_text.to_owned(), false_text.to_owned())),
+                    None => return Err(self.error(span.clone(), "Expected bool(<true>|<false>)")),
+                }
+            },
+            choices if choices.contains('|') => {
+                SegmentType::Choice(choices.split('|').map(str::to_owned).collect())
+            },
+            custom => match self.registry.get(custom) {
+                Some(custom_type) => SegmentType::Custom(custom_type.clone()),
+                None => return Err(PathSchemaParseError::UnrecognizedType{name: custom.to_owned(), span: span.clone()}),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(path: &str) -> Result<Schema, PathSchemaParseError> {
+        parse_schema(path, &SegmentTypeRegistry::new())
+    }
+
+    fn error(path: &str) -> (String, Range<usize>) {
+        let error = parse(path).unwrap_err();
+        (error.to_string(), error.span())
+    }
+
+    #[test]
+    fn test_empty_segments() {
+        assert_eq!(parse("").unwrap(), Schema::new());
+        assert_eq!(parse("/").unwrap(), Schema::new().literal(""));
+        assert_eq!(parse("//").unwrap(), Schema::new().literal("").literal(""));
+        assert_eq!(parse("/users/").unwrap(), Schema::new().literal("users").literal(""));
+        assert_eq!(parse("/é/<ü>").unwrap(), Schema::new().literal("é").value("ü", SegmentType::String));
+        assert_eq!(parse("?<sort?>").unwrap(), Schema::new().optional_query_param("sort", SegmentType::String));
+        assert_eq!(parse("/<..>").unwrap(), Schema::new().match_mode(MatchMode::Prefix));
+        assert_eq!(parse("/<org.id:u64>?<org.name>").unwrap(), Schema::new().value("org.id", SegmentType::U64).query_param("org.name", SegmentType::String));
+        assert_eq!(parse("/a/<b?>/<..>?<c>").unwrap(), Schema::new().literal("a").optional_value("b", SegmentType::String).query_param("c", SegmentType::String).match_mode(MatchMode::Prefix));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(
+            parse(r"/a\<b\>/\:\?\/\\/<c>\&").unwrap(),
+            Schema::new()
+                .literal("a<b>")
+                .literal(r":?/\")
+                .pattern(vec![PatternPart::value("c", SegmentType::String), PatternPart::literal("&")]),
+            );
+        let schema = parse(r"/<id:String(^\d+\/\<\>$)>").unwrap();
+        match &schema.segments()[0] {
+            SegmentSchema::Value(value) => assert_eq!(value.constraint().unwrap().to_string(), r"^\d+/<>$"),
+            segment => panic!("unexpected segment {:?}", segment),
+        }
+        assert_eq!(error(r"/a\"), (r#"Path schema syntax error in "\\" at byte 2: Expected a character to escape after '\'"#.to_owned(), 2..3));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error("/users/<id:u64"), ("Path schema syntax error in \"<id:u64\" at byte 7: Expected '>' to close value".to_owned(), 7..14));
+        assert_eq!(error("/users/id>").1, 9..10);
+        assert_eq!(error("/users/<a<b>").1, 9..10);
+        assert_eq!(error("/<a:u8>/<b:u9>"), ("Unrecognized type \"u9\" at byte 8".to_owned(), 8..14));
+        assert_eq!(error("/<a>/<b>?<a:u8>"), ("Value name \"a\" at byte 9 is used more than once".to_owned(), 9..15));
+        assert_eq!(error("/<a b>").1, 1..6);
+        assert_eq!(error("/<1a>").1, 1..5);
+        assert_eq!(error("/<>").1, 1..3);
+        assert_eq!(error("users").1, 0..5);
+        assert_eq!(error("/<rest..>/more").1, 10..14);
+        assert_eq!(error("/<a?>/b").1, 6..7);
+        assert_eq!(error("/x?<a>&b").1, 7..8);
+        assert_eq!(error("/x?<a>&").1, 2..7);
+        assert_eq!(error("/<..>/a").1, 1..5);
+        assert_eq!(error("/<rest..>/<..>").1, 10..14);
+        assert_eq!(error("/<org.>").1, 1..7);
+        assert_eq!(error("/<org>/<org.id>"), ("Path schema syntax error in \"<org.id>\" at byte 7: Value name \"org.id\" clashes with \"org\"".to_owned(), 7..15));
+        assert_eq!(error("/<org.id>/<org>").1, 10..15);
+    }
+}
@@ -29,17 +29,89 @@
 //! }
 //! ```
 
+extern crate percent_encoding;
 extern crate serde;
 extern crate thiserror;
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
+use std::char::ParseCharError;
 use std::num::{ParseFloatError, ParseIntError};
-use serde::de::Visitor;
-use std::fmt::Display;
+use std::ops::Range;
+use std::str::Utf8Error;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::de::{IntoDeserializer, Visitor};
+use std::fmt::{self, Display};
+
+mod compiled;
+mod conflicts;
+mod constraint;
+mod custom;
+mod display;
+pub mod display_fromstr;
+mod grammar;
+mod pattern;
+mod router;
+mod schema_serde;
+pub mod structured;
+
+pub use percent_encoding::AsciiSet;
+pub use compiled::{Captures, CompiledSchema};
+pub use custom::{register_predicate, register_segment_type, CustomType, FromStrParser, SegmentParser, SegmentTypeRegistry};
+pub use conflicts::{find_conflicts, Conflict, ConflictKind, SegmentConflict};
+pub use constraint::Constraint;
+pub use pattern::PatternPart;
+pub use router::{EnumRouter, RouteMatch, Router};
+
+/// The default set of characters which are percent-encoded when generating a path
+///
+/// This is every character which is not allowed unescaped in a path segment by RFC 3986 (i.e. not
+/// an unreserved character, a sub-delimiter, `:` or `@`).
+pub const PATH_SEGMENT_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'!')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'*')
+    .remove(b'+')
+    .remove(b',')
+    .remove(b';')
+    .remove(b'=')
+    .remove(b':')
+    .remove(b'@');
+
+/// QUERY_ENCODE_SET is the set of characters percent-encoded in query parameter names and values
+///
+/// This is every character which is not allowed unescaped in a query by RFC 3986, along with `&`,
+/// `=` and `+` which separate or mean something else in query parameters.
+pub const QUERY_ENCODE_SET: &AsciiSet = &PATH_SEGMENT_ENCODE_SET
+    .add(b'&')
+    .add(b'=')
+    .add(b'+');
 
 /// SegmentType is a basic enum for specifying what type a segment's value is.
-#[derive(PartialEq, Debug)]
+///
+/// `Enum` is a segment parsed into a unit variant of an enum, any segment matches but parsing
+/// fails unless it names one of the variants
+///
+/// `Choice` is a segment which must be one of a fixed set of strings, it can be parsed into either
+/// an enum or a `String`. There must be at least two, and none may contain `|`, `(`, `)`, `?`, `\`
+/// or `..`, so that the choice can be written in a path schema.
+///
+/// `Bool` is a segment which must be one of the two strings in its `BoolFormat`
+///
+/// `Char` is a segment which must be exactly one character
+///
+/// `Custom` is a segment checked by a `SegmentParser`, it can be parsed into a `String` or, with
+/// the `display_fromstr` module, any type implementing `FromStr` and `Display`
+#[derive(PartialEq, Debug, Clone)]
 pub enum SegmentType {
     F32,
     F64,
@@ -54,15 +126,100 @@ pub enum SegmentType {
     U64,
     U128,
     String,
+    Enum,
+    Choice(Vec<String>),
+    Bool(BoolFormat),
+    Char,
+    Custom(CustomType),
+}
+
+impl SegmentType {
+    /// A custom `SegmentType` checked by `parser`, for use with the builder pattern
+    ///
+    /// To use a custom type in path schema strings, register it instead, see
+    /// `register_segment_type`.
+    pub fn custom<P: SegmentParser + 'static>(parser: P) -> Self {
+        SegmentType::Custom(CustomType::new(parser))
+    }
+}
+
+/// BoolFormat is the text used for `true` and `false` in a `SegmentType::Bool` segment
+///
+/// Parsing only accepts exactly these two strings. Defaults to `BoolFormat::TRUE_FALSE`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct BoolFormat {
+    true_text: Cow<'static, str>,
+    false_text: Cow<'static, str>,
+}
+
+impl BoolFormat {
+    /// `true` and `false`
+    pub const TRUE_FALSE: BoolFormat = BoolFormat{true_text: Cow::Borrowed("true"), false_text: Cow::Borrowed("false")};
+    /// `1` and `0`
+    pub const ONE_ZERO: BoolFormat = BoolFormat{true_text: Cow::Borrowed("1"), false_text: Cow::Borrowed("0")};
+    /// `yes` and `no`
+    pub const YES_NO: BoolFormat = BoolFormat{true_text: Cow::Borrowed("yes"), false_text: Cow::Borrowed("no")};
+
+    /// Create a `BoolFormat` from the text for `true` and `false`
+    pub fn new<T: Into<Cow<'static, str>>, F: Into<Cow<'static, str>>>(true_text: T, false_text: F) -> Self {
+        Self{true_text: true_text.into(), false_text: false_text.into()}
+    }
+
+    /// The text for `true`
+    pub fn true_text(&self) -> &str {
+        &self.true_text
+    }
+
+    /// The text for `false`
+    pub fn false_text(&self) -> &str {
+        &self.false_text
+    }
+}
+
+impl Default for BoolFormat {
+    fn default() -> Self {
+        Self::TRUE_FALSE
+    }
 }
 
 /// SegmentValueSchema holds the schema for a particular value segment.
 ///
 /// See `SegmentSchema` for more details.
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct SegmentValueSchema {
     name: String,
     segment_type: SegmentType,
+    constraint: Option<Constraint>,
+}
+
+impl SegmentValueSchema {
+    /// The name of the field this segment's value is parsed into
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The type of this segment's value
+    pub fn segment_type(&self) -> &SegmentType {
+        &self.segment_type
+    }
+
+    /// The constraint this segment's value must satisfy, if any
+    pub fn constraint(&self) -> Option<&Constraint> {
+        self.constraint.as_ref()
+    }
+
+    /// Parse a single (already percent-decoded) segment, checking it satisfies any constraint
+    fn parse(&self, segment: &str) -> Result<SegmentValue, StructPathError> {
+        let value = parse_segment_value(segment, &self.segment_type)?;
+        match &self.constraint {
+            Some(constraint) if !constraint.check(segment, &value) => Err(StructPathError::ConstraintViolation{
+                field: self.name.clone(),
+                constraint: constraint.to_string(),
+                got: segment.to_owned(),
+            }),
+            _ => Ok(value),
+        }
+    }
 }
 
 /// SegmentValue holds a parsed value
@@ -83,38 +240,204 @@ pub enum SegmentValue {
     U64(u64),
     U128(u128),
     String(String),
+    Bool(bool),
+    Char(char),
+    Custom{
+        segment_type: String,
+        value: String,
+    },
+    Sequence(Vec<SegmentValue>),
 }
 
+/// Writes the value as it appears in a path, e.g. `true` or `42`, with the elements of a
+/// `Sequence` joined with `/`
+impl fmt::Display for SegmentValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SegmentValue::F32(value) => write!(f, "{}", value),
+            SegmentValue::F64(value) => write!(f, "{}", value),
+            SegmentValue::I8(value) => write!(f, "{}", value),
+            SegmentValue::I16(value) => write!(f, "{}", value),
+            SegmentValue::I32(value) => write!(f, "{}", value),
+            SegmentValue::I64(value) => write!(f, "{}", value),
+            SegmentValue::I128(value) => write!(f, "{}", value),
+            SegmentValue::U8(value) => write!(f, "{}", value),
+            SegmentValue::U16(value) => write!(f, "{}", value),
+            SegmentValue::U32(value) => write!(f, "{}", value),
+            SegmentValue::U64(value) => write!(f, "{}", value),
+            SegmentValue::U128(value) => write!(f, "{}", value),
+            SegmentValue::String(value) | SegmentValue::Custom{value, ..} => write!(f, "{}", value),
+            SegmentValue::Bool(value) => write!(f, "{}", value),
+            SegmentValue::Char(value) => write!(f, "{}", value),
+            SegmentValue::Sequence(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "/")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Serializes the value as whichever type it holds, a `Custom` value as its text
+impl serde::Serialize for SegmentValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        match self {
+            SegmentValue::F32(value) => serializer.serialize_f32(*value),
+            SegmentValue::F64(value) => serializer.serialize_f64(*value),
+            SegmentValue::I8(value) => serializer.serialize_i8(*value),
+            SegmentValue::I16(value) => serializer.serialize_i16(*value),
+            SegmentValue::I32(value) => serializer.serialize_i32(*value),
+            SegmentValue::I64(value) => serializer.serialize_i64(*value),
+            SegmentValue::I128(value) => serializer.serialize_i128(*value),
+            SegmentValue::U8(value) => serializer.serialize_u8(*value),
+            SegmentValue::U16(value) => serializer.serialize_u16(*value),
+            SegmentValue::U32(value) => serializer.serialize_u32(*value),
+            SegmentValue::U64(value) => serializer.serialize_u64(*value),
+            SegmentValue::U128(value) => serializer.serialize_u128(*value),
+            SegmentValue::String(value) | SegmentValue::Custom{value, ..} => serializer.serialize_str(value),
+            SegmentValue::Bool(value) => serializer.serialize_bool(*value),
+            SegmentValue::Char(value) => serializer.serialize_char(*value),
+            SegmentValue::Sequence(values) => serializer.collect_seq(values),
+        }
+    }
+}
 
 /// SegmentSchema is the schema for a particular path segment
 ///
 /// `Literal` is a schema for an invairant string literal segment
 ///
 /// `Value` is a schema for a segment containing a value to be parsed
-#[derive(PartialEq, Debug)]
+///
+/// `Optional` is a schema for a value segment which may be left out of the path, only other
+/// `Optional` segments may follow it
+///
+/// `Tail` is a schema for all remaining segments of the path (possibly none), it must be the last
+/// segment in a `Schema`
+///
+/// `Pattern` is a schema for a segment mixing literal text with one or more values, e.g.
+/// `<name>.<ext>`, see `PatternPart`
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SegmentSchema {
     Literal(String),
     Value(SegmentValueSchema),
+    Optional(SegmentValueSchema),
+    Tail(String),
+    Pattern(Vec<PatternPart>),
+}
+
+/// QueryParamSchema is the schema for a query string parameter
+///
+/// `Required` is a schema for a parameter which must appear exactly once
+///
+/// `Optional` is a schema for a parameter which may appear at most once, it should be parsed into
+/// an `Option` field
+///
+/// `Repeated` is a schema for a parameter which may appear any number of times, it should be
+/// parsed into a `Vec` field
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryParamSchema {
+    Required(SegmentValueSchema),
+    Optional(SegmentValueSchema),
+    Repeated(SegmentValueSchema),
+}
+
+impl QueryParamSchema {
+    /// The name and type of this parameter's value
+    pub fn value_schema(&self) -> &SegmentValueSchema {
+        match self {
+            QueryParamSchema::Required(segment_value_schema)
+                | QueryParamSchema::Optional(segment_value_schema)
+                | QueryParamSchema::Repeated(segment_value_schema) => segment_value_schema,
+        }
+    }
+}
+
+/// MatchMode controls how a `Schema` treats paths with more segments than it describes
+///
+/// `Strict` requires the path to have exactly as many segments as the `Schema`, this is the
+/// default.
+///
+/// `Prefix` only requires the `Schema` to match the start of the path, any extra trailing segments
+/// are ignored.
+#[derive(PartialEq, Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    #[default]
+    Strict,
+    Prefix,
+}
+
+/// UnusedKeys controls what generating a path does with fields and map keys a `Schema` has no
+/// value for
+///
+/// `Ignore` leaves them out, this is the default. `Deny` fails with
+/// `StructPathError::UnknownParameter`, and `Query` adds them to the query string after the
+/// `Schema`'s own query parameters, sorted by name.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum UnusedKeys {
+    #[default]
+    Ignore,
+    Deny,
+    Query,
 }
 
 /// Schema hold the schema definition for a particular url path pattern.
 ///
 /// Generally a `Schema` will map 1-to-1 to a particular request handler.
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Schema {
     segments: Vec<SegmentSchema>,
+    query: Vec<QueryParamSchema>,
+    match_mode: MatchMode,
+    encode_set: &'static AsciiSet,
+    deny_unused_values: bool,
+    unused_keys: UnusedKeys,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Error type for parsing Schemas from a String
+///
+/// Each error holds the byte range of the path schema it refers to, see `span`.
 #[derive(Error, Debug)]
 pub enum PathSchemaParseError {
-    #[error("Path schema syntax error in {segment:?}: {message}")]
+    #[error("Path schema syntax error in {segment:?} at byte {}: {message}", span.start)]
     SyntaxError{
         segment: String,
         message: String,
+        span: Range<usize>,
+    },
+    #[error("Unrecognized type {name:?} at byte {}", span.start)]
+    UnrecognizedType{
+        name: String,
+        span: Range<usize>,
     },
-    #[error("Unrecognized type: {0}")]
-    UnrecognizedType(String),
+    #[error("Value name {name:?} at byte {} is used more than once", span.start)]
+    DuplicateName{
+        name: String,
+        span: Range<usize>,
+    },
+}
+
+impl PathSchemaParseError {
+    /// The byte range of the path schema this error refers to
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            PathSchemaParseError::SyntaxError{span, ..}
+                | PathSchemaParseError::UnrecognizedType{span, ..}
+                | PathSchemaParseError::DuplicateName{span, ..} => span.clone(),
+        }
+    }
 }
 
 /// Schema for a url path
@@ -137,6 +460,37 @@ pub enum PathSchemaParseError {
 /// - literal "bar"
 /// - String field bar
 ///
+/// A value segment typed `enum`, e.g. `"/issues/<status:enum>"`, is parsed into a unit variant of
+/// an enum field, respecting serde's `rename` and `rename_all`. A value segment typed as choices
+/// separated by `|`, e.g. `"/issues/<status:open|closed>"`, only matches one of those choices.
+///
+/// A value segment typed `bool` matches `true` or `false`, other forms can be given as e.g.
+/// `"/items/<archived:bool(yes|no)>"`. A value segment typed `char` matches a single character.
+///
+/// A value segment written with a trailing `?`, e.g. `"/items/<page:u32?>"`, is optional and may
+/// be left off the end of the path. Optional segments should be parsed into `Option` fields.
+///
+/// A segment written as `<name..>` captures the rest of the path, e.g. `"/repo/<name>/blob/<path..>"`
+/// matches `"/repo/structpath/blob/src/lib.rs"`. The captured segments can be parsed into either
+/// a `String` (joined with `/`) or a `Vec<String>`.
+///
+/// Query string parameters follow a `?`, separated by `&`, e.g. `"/search/<term>?<page:u32>&<sort?>&<tag..>"`
+/// matches `"/search/rust?page=2&tag=web&tag=cli"`. `<name?>` parameters are optional and
+/// `<name..>` parameters may be repeated, these should be parsed into `Option` and `Vec` fields.
+/// Unknown parameters are ignored when parsing. Paths are always split at the first `?`, even if
+/// the `Schema` has no query parameters.
+///
+/// A struct doesn't need a field for every value, values it has no field for are checked and then
+/// ignored (see `Schema::deny_unused_values`), while fields with no value fall back to `None` or
+/// their `#[serde(default)]`.
+///
+/// Value names must be identifiers, used at most once per schema. A literal `<`, `>`, `?`, `/` or
+/// `\` is written with a `\` before it, e.g. `"/a\\<b"` matches `"/a%3Cb"`. Errors point to the
+/// byte range of the schema they come from, see `PathSchemaParseError::span`.
+///
+/// A schema ending in `/<..>`, e.g. `"/static/<..>"`, matches any path starting with it, see
+/// `MatchMode::Prefix`. A `Schema` can be written back out as a path schema with `to_string`.
+///
 /// ## Using builder pattern
 ///
 /// A more verbose, but a bit more explicit way to create the same value as above is to use the
@@ -155,80 +509,323 @@ pub enum PathSchemaParseError {
 impl Schema {
     /// Create a blank Schema, typically done when using builder pattern
     pub fn new() -> Self {
-        Self{segments: vec![]}
+        Self{
+            segments: vec![],
+            query: vec![],
+            match_mode: MatchMode::Strict,
+            encode_set: PATH_SEGMENT_ENCODE_SET,
+            deny_unused_values: false,
+            unused_keys: UnusedKeys::Ignore,
+        }
     }
 
     /// Create a Schema from a path schema string, see above example.
+    ///
+    /// Custom segment types are looked up in the global registry, see `register_segment_type`.
     pub fn path<S: Into<String>>(path: S) -> Result<Self, PathSchemaParseError> {
-        let mut schema = Schema::new();
-        for segment in path.into().split("/").skip(1) {
-            if &segment[0..1] == "<" {
-                let no_brackets: String = segment.chars().skip(1).take_while(|c| c != &'>').collect();
-                let chunks: Vec<&str> = no_brackets.split(":").collect();
-                if chunks.len() > 2 {
-                    return Err(PathSchemaParseError::SyntaxError{
-                        segment: segment.to_owned(),
-                        message: "Expected at most one ':' in path segment".to_owned(),
-                    });
-                } else if chunks.len() == 2 {
-                    let name = chunks[0];
-                    let segment_type = match chunks[1] {
-                        "f32" => SegmentType::F32,
-                        "f64" => SegmentType::F64,
-                        "u8" => SegmentType::U8,
-                        "u16" => SegmentType::U16,
-                        "u32" => SegmentType::U32,
-                        "u64" => SegmentType::U64,
-                        "u128" => SegmentType::U128,
-                        "i8" => SegmentType::I8,
-                        "i16" => SegmentType::I16,
-                        "i32" => SegmentType::I32,
-                        "i64" => SegmentType::I64,
-                        "i128" => SegmentType::I128,
-                        "String" => SegmentType::String,
-                        _ => {
-                            return Err(PathSchemaParseError::UnrecognizedType(chunks[1].to_owned()))
-                        },
-                    };
-                    schema.segments.push(SegmentSchema::Value(SegmentValueSchema{
-                        name: name.to_owned(),
-                        segment_type: segment_type,
-                    }))
-                } else { // chunks.len() == 1
-                    schema.segments.push(SegmentSchema::Value(SegmentValueSchema{
-                        name: chunks[0].to_owned(),
-                        segment_type: SegmentType::String,
-                    }));
-                }
-            } else {
-                schema.segments.push(SegmentSchema::Literal(segment.to_owned()));
+        custom::with_global_registry(|registry| Self::path_with_registry(path, registry))
+    }
+
+    /// Create a Schema from a path schema string, looking up custom segment types in `registry`
+    pub fn path_with_registry<S: Into<String>>(path: S, registry: &SegmentTypeRegistry) -> Result<Self, PathSchemaParseError> {
+        grammar::parse_schema(&path.into(), registry)
+    }
+
+    /// The segments making up this `Schema`, in path order
+    pub fn segments(&self) -> &[SegmentSchema] {
+        &self.segments
+    }
+
+    /// The query string parameters of this `Schema`, in the order they are generated
+    pub fn query(&self) -> &[QueryParamSchema] {
+        &self.query
+    }
+
+    /// The number of segments in this `Schema`, a tail segment counts as one
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// The name and type of every value this `Schema` captures, path segments first then query
+    /// parameters
+    ///
+    /// A tail segment is listed as a `SegmentType::String`, the type its segments are parsed as.
+    pub fn fields(&self) -> Vec<(&str, &SegmentType)> {
+        let mut fields = vec![];
+        for segment_schema in &self.segments {
+            match segment_schema {
+                SegmentSchema::Literal(_) => (),
+                SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema) => {
+                    fields.push((segment_value_schema.name(), segment_value_schema.segment_type()));
+                },
+                SegmentSchema::Tail(name) => fields.push((name.as_str(), &TAIL_SEGMENT.segment_type)),
+                SegmentSchema::Pattern(parts) => fields.extend(parts.iter().filter_map(|part| match part {
+                    PatternPart::Value(segment_value_schema) => Some((segment_value_schema.name(), segment_value_schema.segment_type())),
+                    PatternPart::Literal(_) => None,
+                })),
             }
         }
-        Ok(schema)
+        fields.extend(self.query.iter().map(|query_param_schema| {
+            let segment_value_schema = query_param_schema.value_schema();
+            (segment_value_schema.name(), segment_value_schema.segment_type())
+        }));
+        fields
+    }
+
+    /// Whether this `Schema` matches only a single path (ignoring any query string), i.e. it has
+    /// only literal segments, no query parameters and `MatchMode::Strict`
+    pub fn is_static(&self) -> bool {
+        self.segments.iter().all(|segment_schema| matches!(segment_schema, SegmentSchema::Literal(_)))
+            && self.query.is_empty()
+            && self.match_mode == MatchMode::Strict
+    }
+
+    /// The start of every path this `Schema` matches, made up of its leading literal segments and
+    /// percent-encoded as in a generated path
+    ///
+    /// e.g. `"/users/all"` for `Schema::path("/users/all/<page:u32>")`, or `""` if the first
+    /// segment isn't a literal
+    pub fn literal_prefix(&self) -> String {
+        self.segments.iter()
+            .map_while(|segment_schema| match segment_schema {
+                SegmentSchema::Literal(literal) => Some(literal),
+                _ => None,
+            })
+            .map(|literal| format!("/{}", utf8_percent_encode(literal, self.encode_set)))
+            .collect()
+    }
+
+    /// Append a segment to the `Schema`, failing if it can't follow the last one
+    ///
+    /// This is the fallible form of the other builder methods, e.g.
+    /// `Schema::new().try_segment(SegmentSchema::Literal("foo".to_owned()))` is
+    /// `Schema::new().literal("foo")`. Just as `Schema::path` rejects `/<rest..>/foo` and
+    /// `/<page:u32?>/foo`, nothing may follow a tail and only optional values may follow an
+    /// optional value. Patterns must alternate non-empty literal text with values, and values must
+    /// have types the grammar can write, which rules out some `SegmentType::Choice`s. Anything else
+    /// is a `StructPathError::InvalidSegment`.
+    pub fn try_segment(mut self, segment_schema: SegmentSchema) -> Result<Self, StructPathError> {
+        if let Err(message) = grammar::check_segment(self.segments.last(), &segment_schema) {
+            return Err(StructPathError::InvalidSegment(format!("{}, can't append {:?}", message, segment_schema)));
+        }
+        self.segments.push(segment_schema);
+        Ok(self)
+    }
+
+    /// Append a segment, panicking where `try_segment` would fail
+    fn push_segment(self, segment_schema: SegmentSchema) -> Self {
+        self.try_segment(segment_schema).unwrap_or_else(|error| panic!("{}", error))
     }
 
     /// Append a literal to the `Schema`
     ///
     /// e.g. `Schema::new().literal("foo")` would match the path `"/foo"`
-    pub fn literal<S: Into<String>>(mut self, segment_literal: S) -> Self {
-        self.segments.push(SegmentSchema::Literal(segment_literal.into()));
-        self
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last segment is a tail or an optional value, just as `Schema::path` rejects
+    /// `/<rest..>/foo` and `/<page:u32?>/foo`.
+    pub fn literal<S: Into<String>>(self, segment_literal: S) -> Self {
+        self.push_segment(SegmentSchema::Literal(segment_literal.into()))
     }
 
     /// Append a value to the `Schema`
     ///
     /// e.g. `Schema::new().value("foo", SegmentType::I64)` is equivalent to
     /// `Schema::path("/<foo:i64>")`
-    pub fn value<S: Into<String>>(mut self, name: S, segment_type: SegmentType) -> Self {
-        self.segments.push(SegmentSchema::Value(SegmentValueSchema{name: name.into(), segment_type: segment_type}));
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last segment is a tail or an optional value, or `segment_type` is a `Choice`
+    /// which can't be written in a path schema, see `try_segment`.
+    pub fn value<S: Into<String>>(self, name: S, segment_type: SegmentType) -> Self {
+        self.push_segment(SegmentSchema::Value(SegmentValueSchema{name: name.into(), segment_type, constraint: None}))
+    }
+
+    /// Append a value to the `Schema` which must satisfy a `Constraint`
+    ///
+    /// e.g. `Schema::new().constrained_value("page", SegmentType::U32, constraint)` is equivalent to
+    /// `Schema::path("/<page:u32(1..=1000)>")` when `constraint` is the range `1..=1000`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last segment is a tail or an optional value, or on an invalid `Choice`, like
+    /// `value`.
+    pub fn constrained_value<S: Into<String>>(self, name: S, segment_type: SegmentType, constraint: Constraint) -> Self {
+        self.push_segment(SegmentSchema::Value(SegmentValueSchema{name: name.into(), segment_type, constraint: Some(constraint)}))
+    }
+
+    /// Append a value to the `Schema` which must satisfy a predicate
+    ///
+    /// e.g. `Schema::new().value_with("slug", SegmentType::String, "non_empty", |slug| !slug.is_empty())`,
+    /// the predicate is given the percent-decoded segment. It's written as
+    /// `<slug:String(@non_empty)>`, which only parses back once `register_predicate` has been
+    /// given a predicate called `non_empty`.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `constrained_value`.
+    pub fn value_with<S, P, F>(self, name: S, segment_type: SegmentType, predicate_name: P, predicate: F) -> Self
+        where S: Into<String>, P: Into<String>, F: Fn(&str) -> bool + Send + Sync + 'static {
+        self.constrained_value(name, segment_type, Constraint::predicate(predicate_name, predicate))
+    }
+
+    /// Append an optional value to the `Schema`
+    ///
+    /// e.g. `Schema::new().optional_value("page", SegmentType::U32)` is equivalent to
+    /// `Schema::path("/<page:u32?>")`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last segment is a tail or `segment_type` is an invalid `Choice`, and appending
+    /// anything but another optional value after it panics too, since the path may stop before
+    /// any optional value.
+    pub fn optional_value<S: Into<String>>(self, name: S, segment_type: SegmentType) -> Self {
+        self.push_segment(SegmentSchema::Optional(SegmentValueSchema{name: name.into(), segment_type, constraint: None}))
+    }
+
+    /// Append a segment mixing literal text with values to the `Schema`
+    ///
+    /// e.g. `Schema::new().pattern(vec![PatternPart::value("name", SegmentType::String),
+    /// PatternPart::literal("."), PatternPart::value("ext", SegmentType::String)])` is equivalent
+    /// to `Schema::path("/<name>.<ext>")`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last segment is a tail or an optional value, since a pattern always takes up
+    /// a whole segment, if the parts lack either a value or literal text, if two values or two
+    /// literals are next to each other, if a literal is empty, or if any of the values is an
+    /// invalid `Choice`.
+    pub fn pattern(self, parts: Vec<PatternPart>) -> Self {
+        self.push_segment(SegmentSchema::Pattern(parts))
+    }
+
+    /// Append a tail to the `Schema`, capturing all remaining segments of the path
+    ///
+    /// e.g. `Schema::new().literal("static").tail("path")` is equivalent to
+    /// `Schema::path("/static/<path..>")`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last segment is a tail or an optional value, and any segment appended after
+    /// a tail panics too.
+    pub fn tail<S: Into<String>>(self, name: S) -> Self {
+        self.push_segment(SegmentSchema::Tail(name.into()))
+    }
+
+    /// Add a query string parameter to the `Schema`, failing if its type can't be written in a
+    /// path schema, like `try_segment`
+    pub fn try_query_param(mut self, query_param_schema: QueryParamSchema) -> Result<Self, StructPathError> {
+        if let Err(message) = grammar::check_segment_type(&query_param_schema.value_schema().segment_type) {
+            return Err(StructPathError::InvalidSegment(format!("{}, can't add {:?}", message, query_param_schema)));
+        }
+        self.query.push(query_param_schema);
+        Ok(self)
+    }
+
+    /// Add a query string parameter, panicking where `try_query_param` would fail
+    fn push_query_param(self, query_param_schema: QueryParamSchema) -> Self {
+        self.try_query_param(query_param_schema).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Add a required query string parameter to the `Schema`
+    ///
+    /// e.g. `Schema::new().literal("search").query_param("page", SegmentType::U32)` is equivalent
+    /// to `Schema::path("/search?<page:u32>")`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `segment_type` is a `Choice` which can't be written in a path schema, as do
+    /// `optional_query_param` and `repeated_query_param`.
+    pub fn query_param<S: Into<String>>(self, name: S, segment_type: SegmentType) -> Self {
+        self.push_query_param(QueryParamSchema::Required(SegmentValueSchema{name: name.into(), segment_type, constraint: None}))
+    }
+
+    /// Add an optional query string parameter to the `Schema`
+    ///
+    /// e.g. `Schema::new().optional_query_param("sort", SegmentType::String)` is equivalent to
+    /// `Schema::path("?<sort?>")`
+    pub fn optional_query_param<S: Into<String>>(self, name: S, segment_type: SegmentType) -> Self {
+        self.push_query_param(QueryParamSchema::Optional(SegmentValueSchema{name: name.into(), segment_type, constraint: None}))
+    }
+
+    /// Add a repeated query string parameter to the `Schema`
+    ///
+    /// e.g. `Schema::new().repeated_query_param("tag", SegmentType::String)` is equivalent to
+    /// `Schema::path("?<tag..>")`
+    pub fn repeated_query_param<S: Into<String>>(self, name: S, segment_type: SegmentType) -> Self {
+        self.push_query_param(QueryParamSchema::Repeated(SegmentValueSchema{name: name.into(), segment_type, constraint: None}))
+    }
+
+    /// Set how paths with extra trailing segments are handled, see `MatchMode`
+    ///
+    /// e.g. `Schema::path("/foo")?.match_mode(MatchMode::Prefix)` would match both `"/foo"` and
+    /// `"/foo/bar"`
+    pub fn match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.match_mode = match_mode;
+        self
+    }
+
+    /// Set which characters are percent-encoded when generating a path
+    ///
+    /// Defaults to `PATH_SEGMENT_ENCODE_SET`. Parsing always percent-decodes every segment
+    /// regardless of this setting.
+    pub fn encode_set(mut self, encode_set: &'static AsciiSet) -> Self {
+        self.encode_set = encode_set;
+        self
+    }
+
+    /// Set whether parsing fails when the target has no use for a value this `Schema` captures
+    ///
+    /// By default such values are ignored, though they're still checked against the `Schema`, so
+    /// a struct can leave out values it doesn't need. With this set, they cause a
+    /// `StructPathError::UnusedValue` instead. Generating is configured with `unused_keys`.
+    pub fn deny_unused_values(mut self, deny_unused_values: bool) -> Self {
+        self.deny_unused_values = deny_unused_values;
+        self
+    }
+
+    /// Set what generating a path does with fields and map keys this `Schema` has no value for,
+    /// see `UnusedKeys`
+    ///
+    /// e.g. generating `{"id": 1, "debug": true}` with `Schema::path("/items/<id:u32>")` and
+    /// `UnusedKeys::Query` gives `"/items/1?debug=true"`.
+    pub fn unused_keys(mut self, unused_keys: UnusedKeys) -> Self {
+        self.unused_keys = unused_keys;
         self
     }
 
     /// Parse a concrete path into a value, using this `Schema`
-    pub fn parse<'a, S, T>(&self, path: S) -> Result<T, StructPathError> where S: Into<String>, T: serde::Deserialize<'a> {
+    pub fn parse<S, T>(&self, path: S) -> Result<T, StructPathError> where S: Into<String>, T: serde::de::DeserializeOwned {
         parse_path(path, self)
     }
 
+    /// Parse a concrete path into a value which may borrow from the path, using this `Schema`
+    ///
+    /// This avoids allocating wherever possible, see `parse_path_str`.
+    pub fn parse_str<'de, T>(&self, path: &'de str) -> Result<T, StructPathError> where T: serde::Deserialize<'de> {
+        parse_path_str(path, self)
+    }
+
+    /// Parse a concrete path into its names and values, in the order they appear in this
+    /// `Schema`, without deserializing
+    ///
+    /// Useful when the values aren't known ahead of time, e.g. to log or forward them. Absent
+    /// optional values are left out, while tails and repeated query parameters are
+    /// `SegmentValue::Sequence`s.
+    pub fn parse_generic(&self, path: &str) -> Result<Vec<(String, SegmentValue)>, StructPathError> {
+        parse_values(path, self)
+    }
+
+    /// The path schema string this `Schema` would be parsed from, e.g. `"/users/<id:u64>"`
+    ///
+    /// Unlike `to_string`, this fails with `StructPathError::NoPathSchemaForm` unless
+    /// `Schema::path` gives back the same segments, query parameters and match mode, e.g. for a
+    /// value name used twice. Custom types and predicates must be registered globally.
+    pub fn to_path_string(&self) -> Result<String, StructPathError> {
+        display::path_string(self).map_err(StructPathError::NoPathSchemaForm)
+    }
+
     /// Create a path String from parameters and this `Schema`
     pub fn generate<T>(&self, parameters: &T) -> Result<String, StructPathError> where T: serde::Serialize {
         generate_path(parameters, self)
@@ -243,10 +840,24 @@ pub enum StructPathError {
         got: String,
         expected: String,
     },
+    #[error("Too many path segments (expected {expected}, got {got})")]
+    TooManySegments{
+        expected: usize,
+        got: usize,
+    },
+    #[error("Too few path segments (expected {expected}, got {got})")]
+    TooFewSegments{
+        expected: usize,
+        got: usize,
+    },
     #[error(transparent)]
     ParseFloatError(#[from] ParseFloatError),
     #[error(transparent)]
     ParseIntError(#[from] ParseIntError),
+    #[error(transparent)]
+    ParseCharError(#[from] ParseCharError),
+    #[error("Invalid percent-encoding in path segment: {0}")]
+    Utf8Error(#[from] Utf8Error),
     #[error("Error from serde: {0}")]
     SerdeInternalError(String),
     #[error("Error is impossible, but reqired structurrally")]
@@ -257,6 +868,39 @@ pub enum StructPathError {
     NotSupported(String),
     #[error("Expected field {0:?} missing from input")]
     MissingField(String),
+    #[error("Unexpected value {got:?}, expected one of {expected:?}")]
+    InvalidChoice{
+        got: String,
+        expected: Vec<String>,
+    },
+    #[error("Invalid {segment_type} {got:?}: {message}")]
+    InvalidCustomValue{
+        segment_type: String,
+        got: String,
+        message: String,
+    },
+    #[error("Value {got:?} for {field:?} does not satisfy the constraint {constraint}")]
+    ConstraintViolation{
+        field: String,
+        constraint: String,
+        got: String,
+    },
+    #[error("Generated segment {0:?} would not parse back into the same values")]
+    AmbiguousPattern(String),
+    #[error("Query parameter {0:?} given more than once")]
+    DuplicateQueryParam(String),
+    #[error("Value {0:?} is captured by the schema but not used")]
+    UnusedValue(String),
+    #[error("Parameter {0:?} has no value in the schema")]
+    UnknownParameter(String),
+    #[error("No route matches the path {0:?}")]
+    NoMatchingRoute(String),
+    #[error("Variant {0:?} has no schema")]
+    UnknownVariant(String),
+    #[error("Schema can't be written as a path schema: {0}")]
+    NoPathSchemaForm(String),
+    #[error("Invalid segment: {0}")]
+    InvalidSegment(String),
     #[error("Expected state(s): {expected}, got {got:?}")]
     InvalidSerializerState{
         expected: String,
@@ -273,6 +917,13 @@ impl serde::de::Error for StructPathError {
     fn custom<T>(msg: T) -> Self where T: Display {
         StructPathError::SerdeInternalError(msg.to_string())
     }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        StructPathError::InvalidChoice{
+            got: variant.to_owned(),
+            expected: expected.iter().map(|variant| variant.to_string()).collect(),
+        }
+    }
 }
 
 impl serde::ser::Error for StructPathError {
@@ -281,59 +932,164 @@ impl serde::ser::Error for StructPathError {
     }
 }
 
-fn parse_path_generic(path: String, schema: &Schema) -> Result<HashMap<String, SegmentValue>, StructPathError> {
-    let mut path_values = HashMap::new();
-    for (segment, segment_schema) in path.split("/").skip(1).zip(schema.segments.iter()) {
+/// Parse a single (already percent-decoded) segment according to its `SegmentType`
+fn parse_segment_value(segment: &str, segment_type: &SegmentType) -> Result<SegmentValue, StructPathError> {
+    Ok(match segment_type {
+        SegmentType::F32 => SegmentValue::F32(segment.parse()?),
+        SegmentType::F64 => SegmentValue::F64(segment.parse()?),
+        SegmentType::I8 => SegmentValue::I8(segment.parse()?),
+        SegmentType::I16 => SegmentValue::I16(segment.parse()?),
+        SegmentType::I32 => SegmentValue::I32(segment.parse()?),
+        SegmentType::I64 => SegmentValue::I64(segment.parse()?),
+        SegmentType::I128 => SegmentValue::I128(segment.parse()?),
+        SegmentType::U8 => SegmentValue::U8(segment.parse()?),
+        SegmentType::U16 => SegmentValue::U16(segment.parse()?),
+        SegmentType::U32 => SegmentValue::U32(segment.parse()?),
+        SegmentType::U64 => SegmentValue::U64(segment.parse()?),
+        SegmentType::U128 => SegmentValue::U128(segment.parse()?),
+        SegmentType::String | SegmentType::Enum => SegmentValue::String(segment.to_owned()),
+        SegmentType::Choice(choices) => {
+            if !choices.iter().any(|choice| choice == segment) {
+                return Err(StructPathError::InvalidChoice{got: segment.to_owned(), expected: choices.clone()});
+            }
+            SegmentValue::String(segment.to_owned())
+        },
+        SegmentType::Bool(bool_format) => {
+            if segment == bool_format.true_text() {
+                SegmentValue::Bool(true)
+            } else if segment == bool_format.false_text() {
+                SegmentValue::Bool(false)
+            } else {
+                return Err(StructPathError::InvalidChoice{
+                    got: segment.to_owned(),
+                    expected: vec![bool_format.true_text().to_owned(), bool_format.false_text().to_owned()],
+                });
+            }
+        },
+        SegmentType::Char => SegmentValue::Char(segment.parse()?),
+        SegmentType::Custom(custom_type) => {
+            custom_type.validate(segment).map_err(|message| StructPathError::InvalidCustomValue{
+                segment_type: custom_type.name().to_owned(),
+                got: segment.to_owned(),
+                message,
+            })?;
+            SegmentValue::Custom{segment_type: custom_type.name().to_owned(), value: segment.to_owned()}
+        },
+    })
+}
+
+/// Split a path into the path itself and its query string, if any
+fn split_query(path: &str) -> (&str, Option<&str>) {
+    match path.find('?') {
+        Some(index) => (&path[..index], Some(&path[index + 1..])),
+        None => (path, None),
+    }
+}
+
+/// Percent-decode a query parameter name or value, treating `+` as a space
+fn decode_query_component(raw: &str) -> Result<Cow<'_, str>, Utf8Error> {
+    if raw.contains('+') {
+        Ok(Cow::Owned(percent_decode_str(&raw.replace('+', " ")).decode_utf8()?.into_owned()))
+    } else {
+        Ok(percent_decode_str(raw).decode_utf8()?)
+    }
+}
+
+/// Find the raw value of the next `name` parameter in a query string, advancing `rest` past it
+fn next_query_value<'q>(rest: &mut Option<&'q str>, name: &str) -> Option<&'q str> {
+    while let Some(query) = rest.take() {
+        let pair = match query.find('&') {
+            Some(index) => {
+                *rest = Some(&query[index + 1..]);
+                &query[..index]
+            },
+            None => query,
+        };
+        let (key, value) = match pair.find('=') {
+            Some(index) => (&pair[..index], &pair[index + 1..]),
+            None => (pair, ""),
+        };
+        if decode_query_component(key).is_ok_and(|key| key == name) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Check a path with `count` segments has the right number of segments for `schema`
+fn check_segment_count(schema: &Schema, count: usize) -> Result<(), StructPathError> {
+    let required = schema.segments.iter()
+        .filter(|segment_schema| matches!(segment_schema, SegmentSchema::Literal(_) | SegmentSchema::Value(_) | SegmentSchema::Pattern(_)))
+        .count();
+    let has_tail = matches!(schema.segments.last(), Some(SegmentSchema::Tail(_)));
+    if count < required {
+        return Err(StructPathError::TooFewSegments{expected: required, got: count});
+    }
+    if count > schema.segments.len() && schema.match_mode == MatchMode::Strict && !has_tail {
+        return Err(StructPathError::TooManySegments{expected: schema.segments.len(), got: count});
+    }
+    Ok(())
+}
+
+/// Parse a particular path using a `Schema` into a map of its values, without deserializing
+pub fn parse_path_generic(path: String, schema: &Schema) -> Result<HashMap<String, SegmentValue>, StructPathError> {
+    Ok(parse_values(&path, schema)?.into_iter().collect())
+}
+
+/// Parse a path into the name and value of each of its values, in the order they appear in `schema`
+fn parse_values(path: &str, schema: &Schema) -> Result<Vec<(String, SegmentValue)>, StructPathError> {
+    let mut path_values = vec![];
+    let (path, query) = split_query(path);
+    let segments: Vec<&str> = path.split("/").skip(1).collect();
+    check_segment_count(schema, segments.len())?;
+    for (index, segment_schema) in schema.segments.iter().enumerate() {
+        if let SegmentSchema::Tail(name) = segment_schema {
+            let mut tail = vec![];
+            for raw_segment in segments.iter().skip(index) {
+                tail.push(SegmentValue::String(percent_decode_str(raw_segment).decode_utf8()?.into_owned()));
+            }
+            path_values.push((name.clone(), SegmentValue::Sequence(tail)));
+            break;
+        }
+        // only optional segments can be left over once the path runs out
+        let raw_segment = match segments.get(index) {
+            Some(raw_segment) => raw_segment,
+            None => break,
+        };
+        let segment: &str = &percent_decode_str(raw_segment).decode_utf8()?;
         match segment_schema {
             SegmentSchema::Literal(literal) => {
                 if segment != literal {
                     return Err(StructPathError::IncorrectSegment{got: segment.to_owned(), expected: literal.clone()});
                 }
             }
-            SegmentSchema::Value(segment_value_schema) => {
-                match segment_value_schema.segment_type {
-                    SegmentType::F32 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::F32(segment.parse()?));
-                    },
-                    SegmentType::F64 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::F64(segment.parse()?));
-                    },
-                    SegmentType::I8 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::I8(segment.parse()?));
-                    },
-                    SegmentType::I16 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::I16(segment.parse()?));
-                    },
-                    SegmentType::I32 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::I32(segment.parse()?));
-                    },
-                    SegmentType::I64 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::I64(segment.parse()?));
-                    },
-                    SegmentType::I128 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::I128(segment.parse()?));
-                    },
-                    SegmentType::U8 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::U8(segment.parse()?));
-                    },
-                    SegmentType::U16 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::U16(segment.parse()?));
-                    },
-                    SegmentType::U32 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::U32(segment.parse()?));
-                    },
-                    SegmentType::U64 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::U64(segment.parse()?));
-                    },
-                    SegmentType::U128 => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::U128(segment.parse()?));
-                    },
-                    SegmentType::String => {
-                        path_values.insert(segment_value_schema.name.clone(), SegmentValue::String(segment.to_owned()));
-                    },
-                }
+            SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema) => {
+                path_values.push((segment_value_schema.name.clone(), segment_value_schema.parse(segment)?));
             },
+            SegmentSchema::Pattern(parts) => for (segment_value_schema, range) in pattern::split_pattern(parts, raw_segment)? {
+                path_values.push((
+                    segment_value_schema.name.clone(),
+                    segment_value_schema.parse(&percent_decode_str(&raw_segment[range]).decode_utf8()?)?,
+                ));
+            },
+            SegmentSchema::Tail(_) => return Err(StructPathError::Impossible),
+        }
+    }
+    for query_param_schema in &schema.query {
+        let segment_value_schema = query_param_schema.value_schema();
+        let mut rest = query;
+        let mut values = vec![];
+        while let Some(raw_value) = next_query_value(&mut rest, &segment_value_schema.name) {
+            values.push(segment_value_schema.parse(&decode_query_component(raw_value)?)?);
         }
+        let value = match (query_param_schema, values.len()) {
+            (QueryParamSchema::Repeated(_), _) => SegmentValue::Sequence(values),
+            (QueryParamSchema::Required(_), 0) => return Err(StructPathError::MissingField(segment_value_schema.name.clone())),
+            (_, 0) => continue,
+            (_, 1) => values.remove(0),
+            _ => return Err(StructPathError::DuplicateQueryParam(segment_value_schema.name.clone())),
+        };
+        path_values.push((segment_value_schema.name.clone(), value));
     }
     Ok(path_values)
 }
@@ -341,230 +1097,509 @@ fn parse_path_generic(path: String, schema: &Schema) -> Result<HashMap<String, S
 /// Internal state for Deserializer, usually only useful for debugging.
 #[derive(Clone, Debug)]
 pub enum DeserializerState {
-    Start,
-    Map,
-    MapKey(String),
-    MapValue(SegmentValue),
-    End,
+    Start,  // starting, expecting a struct or map
+    Map,  // in a map, about to deserialize the next key
+    MapValue,  // in a map, about to deserialize the value for the last key
+    End,  // ending, all values have been deserialized
 }
 
-struct Deserializer {
-    generic_parsed_path: HashMap<String, SegmentValue>,
-    state: DeserializerState,
+/// A captured value in a path, not yet percent-decoded or parsed
+///
+/// `Repeated` holds the whole query string, along with the schema of the parameter to collect.
+/// `Parsed` holds a value which was already parsed, e.g. by `Router::at`, and whether it's a tail.
+#[derive(Clone, Copy)]
+enum Capture<'de, 's> {
+    Value(&'de str, &'s SegmentValueSchema),
+    Tail(Option<&'de str>),
+    QueryValue(&'de str, &'s SegmentValueSchema),
+    Repeated(Option<&'de str>, &'s SegmentValueSchema),
+    Parsed(&'de SegmentValue, bool),
 }
 
-impl <'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer {
-    type Error = StructPathError;
+/// Walks a path and its `Schema` together, collecting the captured values
+struct CaptureWalker<'de, 's> {
+    schema: &'s Schema,
+    rest: Option<&'de str>,
+    index: usize,
+    query: Option<&'de str>,
+    query_index: usize,
+    pattern_captures: Vec<(&'s str, Capture<'de, 's>)>,
+}
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("deserialize_any".to_owned()))
+impl<'de, 's> CaptureWalker<'de, 's> {
+    fn new(path: &'de str, schema: &'s Schema) -> Result<Self, StructPathError> {
+        let (path, query) = split_query(path);
+        check_segment_count(schema, path.matches('/').count())?;
+        Ok(Self{
+            schema,
+            rest: path.find('/').map(|index| &path[index + 1..]),
+            index: 0,
+            query,
+            query_index: 0,
+            pattern_captures: vec![],
+        })
+    }
+
+    fn next_segment(&mut self) -> Option<&'de str> {
+        let rest = self.rest?;
+        match rest.find('/') {
+            Some(index) => {
+                self.rest = Some(&rest[index + 1..]);
+                Some(&rest[..index])
+            },
+            None => {
+                self.rest = None;
+                Some(rest)
+            },
+        }
     }
 
-    fn deserialize_bool<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("bool".to_owned()))
+    /// Advance to the next value in the schema, checking any literals on the way
+    fn next_capture(&mut self) -> Result<Option<(&'s str, Capture<'de, 's>)>, StructPathError> {
+        // the rest of the values from the last pattern, in reverse order
+        if let Some(capture) = self.pattern_captures.pop() {
+            return Ok(Some(capture));
+        }
+        while let Some(segment_schema) = self.schema.segments.get(self.index) {
+            self.index += 1;
+            match segment_schema {
+                SegmentSchema::Literal(literal) => {
+                    let raw_segment = self.next_segment().ok_or(StructPathError::Impossible)?;
+                    let segment = percent_decode_str(raw_segment).decode_utf8()?;
+                    if segment != literal.as_str() {
+                        return Err(StructPathError::IncorrectSegment{got: segment.into_owned(), expected: literal.clone()});
+                    }
+                },
+                SegmentSchema::Value(segment_value_schema) | SegmentSchema::Optional(segment_value_schema) => {
+                    // only optional segments can be left over once the path runs out
+                    if let Some(raw_segment) = self.next_segment() {
+                        let capture = Capture::Value(raw_segment, segment_value_schema);
+                        return Ok(Some((segment_value_schema.name.as_str(), capture)));
+                    }
+                },
+                SegmentSchema::Tail(name) => return Ok(Some((name.as_str(), Capture::Tail(self.rest.take())))),
+                SegmentSchema::Pattern(parts) => {
+                    let raw_segment = self.next_segment().ok_or(StructPathError::Impossible)?;
+                    for (segment_value_schema, range) in pattern::split_pattern(parts, raw_segment)?.into_iter().rev() {
+                        let capture = Capture::Value(&raw_segment[range], segment_value_schema);
+                        self.pattern_captures.push((segment_value_schema.name.as_str(), capture));
+                    }
+                    if let Some(capture) = self.pattern_captures.pop() {
+                        return Ok(Some(capture));
+                    }
+                },
+            }
+        }
+        while let Some(query_param_schema) = self.schema.query.get(self.query_index) {
+            self.query_index += 1;
+            let segment_value_schema = query_param_schema.value_schema();
+            let name = segment_value_schema.name.as_str();
+            if let QueryParamSchema::Repeated(_) = query_param_schema {
+                return Ok(Some((name, Capture::Repeated(self.query, segment_value_schema))));
+            }
+            let mut rest = self.query;
+            match (next_query_value(&mut rest, name), next_query_value(&mut rest, name)) {
+                (Some(_), Some(_)) => return Err(StructPathError::DuplicateQueryParam(name.to_owned())),
+                (Some(raw_value), None) => return Ok(Some((name, Capture::QueryValue(raw_value, segment_value_schema)))),
+                (None, _) => if let QueryParamSchema::Required(_) = query_param_schema {
+                    return Err(StructPathError::MissingField(name.to_owned()));
+                },
+            }
+        }
+        Ok(None)
     }
 
-    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::I8(value) => (DeserializerState::Map, visitor.visit_i8(*value)),
-                _ => return Err(StructPathError::ExpectedType("i8".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+    /// Walk the whole path, collecting every captured value along with its name
+    fn captures(mut self) -> Result<Vec<(&'s str, Capture<'de, 's>)>, StructPathError> {
+        let mut captures = vec![];
+        while let Some(capture) = self.next_capture()? {
+            captures.push(capture);
+        }
+        Ok(captures)
     }
+}
 
-    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::I16(value) => (DeserializerState::Map, visitor.visit_i16(*value)),
-                _ => return Err(StructPathError::ExpectedType("i16".to_owned(), segment_value.clone())),
-            },
+/// A value handed out by `CaptureMap`, either a single capture or every capture nested under
+/// the first part of a dotted name, e.g. `org.id` and `org.name` under `org`
+enum Entry<'de, 's> {
+    /// A capture along with its full name
+    Capture(&'s str, Capture<'de, 's>),
+    /// Captures along with their names relative to the nested struct, and their full names
+    Nested(Vec<(&'s str, &'s str, Capture<'de, 's>)>),
+}
+
+/// Deserializer handing out captured values as a map, grouping dotted names into nested maps
+///
+/// Values are only percent-decoded and parsed as they're deserialized, and nothing is allocated
+/// for a value unless it needs percent-decoding, so `&str` fields can borrow directly from the path.
+struct CaptureMap<'de, 's> {
+    entries: std::vec::IntoIter<(&'s str, Entry<'de, 's>)>,
+    entry: Option<Entry<'de, 's>>,
+    deny_unused_values: bool,
+    state: DeserializerState,
+}
+
+impl<'de, 's> CaptureMap<'de, 's> {
+    /// Group captures given as (relative name, full name, capture), keeping the order each key first appears in
+    fn new(captures: Vec<(&'s str, &'s str, Capture<'de, 's>)>, deny_unused_values: bool) -> Self {
+        let mut entries: Vec<(&'s str, Entry<'de, 's>)> = vec![];
+        for (name, full_name, capture) in captures {
+            match name.split_once('.') {
+                None => entries.push((name, Entry::Capture(full_name, capture))),
+                Some((key, rest)) => match entries.iter_mut().find(|(other, _)| *other == key) {
+                    Some((_, Entry::Nested(nested))) => nested.push((rest, full_name, capture)),
+                    _ => entries.push((key, Entry::Nested(vec![(rest, full_name, capture)]))),
+                },
+            }
+        }
+        Self{entries: entries.into_iter(), entry: None, deny_unused_values, state: DeserializerState::Start}
+    }
+}
+
+impl<'de, 's> serde::de::Deserializer<'de> for CaptureMap<'de, 's> {
+    type Error = StructPathError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        // a path, or a nested struct within it, is always a map of its values
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        self.state = match self.state {
+            DeserializerState::Start => DeserializerState::Map,
             _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
+                expected: "Start".to_owned(),
                 got: self.state.clone(),
-            })
+            }),
         };
-        self.state = next_state;
-        result
+        visitor.visit_map(self)
     }
 
-    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::I32(value) => (DeserializerState::Map, visitor.visit_i32(*value)),
-                _ => return Err(StructPathError::ExpectedType("i32".to_owned(), segment_value.clone())),
-            },
+    fn deserialize_struct<V>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        // a nested struct is only deserialized if at least one of its values was captured
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        // each value is ignored in turn, so they're still checked against the schema
+        self.deserialize_map(serde::de::IgnoredAny)?;
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct enum identifier
+    }
+}
+
+impl<'de, 's> serde::de::MapAccess<'de> for CaptureMap<'de, 's> {
+    type Error = StructPathError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> where K: serde::de::DeserializeSeed<'de> {
+        match self.state {
+            DeserializerState::Map => (),
             _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
+                expected: "Map".to_owned(),
                 got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+            }),
+        }
+        match self.entries.next() {
+            Some((key, entry)) => {
+                self.entry = Some(entry);
+                self.state = DeserializerState::MapValue;
+                seed.deserialize(key.into_deserializer()).map(Some)
+            },
+            None => {
+                self.state = DeserializerState::End;
+                Ok(None)
+            },
+        }
     }
 
-    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::I64(value) => (DeserializerState::Map, visitor.visit_i64(*value)),
-                _ => return Err(StructPathError::ExpectedType("i64".to_owned(), segment_value.clone())),
-            },
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error> where V: serde::de::DeserializeSeed<'de> {
+        let entry = match (&self.state, self.entry.take()) {
+            (DeserializerState::MapValue, Some(entry)) => entry,
             _ => return Err(StructPathError::InvalidDeserializerState{
                 expected: "MapValue".to_owned(),
                 got: self.state.clone(),
-            })
+            }),
         };
-        self.state = next_state;
-        result
+        self.state = DeserializerState::Map;
+        match entry {
+            Entry::Capture(name, capture) => {
+                let unused_name = if self.deny_unused_values { Some(name) } else { None };
+                seed.deserialize(SegmentDeserializer{capture, unused_name})
+            },
+            Entry::Nested(captures) => seed.deserialize(CaptureMap::new(captures, self.deny_unused_values)),
+        }
     }
+}
 
-    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::I128(value) => (DeserializerState::Map, visitor.visit_i128(*value)),
-                _ => return Err(StructPathError::ExpectedType("i128".to_owned(), segment_value.clone())),
+/// Deserializer for an enum, choosing a variant and filling in its fields from the path
+struct VariantDeserializer<'de, 's> {
+    variant: &'s str,
+    map: CaptureMap<'de, 's>,
+}
+
+impl<'de, 's> serde::de::Deserializer<'de> for VariantDeserializer<'de, 's> {
+    type Error = StructPathError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        visitor.visit_enum(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 's> serde::de::EnumAccess<'de> for VariantDeserializer<'de, 's> {
+    type Error = StructPathError;
+    type Variant = CaptureMap<'de, 's>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error> where V: serde::de::DeserializeSeed<'de> {
+        let variant = seed.deserialize(IntoDeserializer::<StructPathError>::into_deserializer(self.variant))?;
+        Ok((variant, self.map))
+    }
+}
+
+impl<'de, 's> serde::de::VariantAccess<'de> for CaptureMap<'de, 's> {
+    type Error = StructPathError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        // a unit variant uses none of the values, but they must still match the schema
+        serde::de::Deserializer::deserialize_ignored_any(self, serde::de::IgnoredAny)?;
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error> where T: serde::de::DeserializeSeed<'de> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        Err(StructPathError::NotSupported("tuple variant".to_owned()))
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        serde::de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+/// Hand a parsed value to a visitor as whichever type it holds
+fn visit_segment_value<'de, V>(segment_value: SegmentValue, visitor: V) -> Result<V::Value, StructPathError> where V: Visitor<'de> {
+    match segment_value {
+        SegmentValue::F32(value) => visitor.visit_f32(value),
+        SegmentValue::F64(value) => visitor.visit_f64(value),
+        SegmentValue::I8(value) => visitor.visit_i8(value),
+        SegmentValue::I16(value) => visitor.visit_i16(value),
+        SegmentValue::I32(value) => visitor.visit_i32(value),
+        SegmentValue::I64(value) => visitor.visit_i64(value),
+        SegmentValue::I128(value) => visitor.visit_i128(value),
+        SegmentValue::U8(value) => visitor.visit_u8(value),
+        SegmentValue::U16(value) => visitor.visit_u16(value),
+        SegmentValue::U32(value) => visitor.visit_u32(value),
+        SegmentValue::U64(value) => visitor.visit_u64(value),
+        SegmentValue::U128(value) => visitor.visit_u128(value),
+        SegmentValue::String(value) | SegmentValue::Custom{value, ..} => visitor.visit_string(value),
+        SegmentValue::Bool(value) => visitor.visit_bool(value),
+        SegmentValue::Char(value) => visitor.visit_char(value),
+        // only tails and repeated query parameters hold sequences, and those are visited lazily
+        SegmentValue::Sequence(_) => Err(StructPathError::Impossible),
+    }
+}
+
+/// Deserializer for a single captured value
+struct SegmentDeserializer<'de, 's> {
+    capture: Capture<'de, 's>,
+    /// The value's name if ignoring it is an error, see `Schema::deny_unused_values`
+    unused_name: Option<&'s str>,
+}
+
+impl<'de, 's> SegmentDeserializer<'de, 's> {
+    /// The percent-decoded text of the capture, only allocated if decoding changed anything
+    fn decoded(&self) -> Result<Cow<'de, str>, StructPathError> {
+        Ok(match self.capture {
+            Capture::Value(raw_segment, _) => percent_decode_str(raw_segment).decode_utf8()?,
+            Capture::Tail(rest) => percent_decode_str(rest.unwrap_or("")).decode_utf8()?,
+            Capture::QueryValue(raw_value, _) => decode_query_component(raw_value)?,
+            Capture::Parsed(SegmentValue::String(value), _) | Capture::Parsed(SegmentValue::Custom{value, ..}, _) => Cow::Borrowed(value),
+            Capture::Parsed(SegmentValue::Sequence(_), false) | Capture::Repeated(..) => return Err(StructPathError::Impossible),
+            Capture::Parsed(segment_value, _) => Cow::Owned(segment_value.to_string()),
+        })
+    }
+
+    /// The capture parsed according to its `SegmentValueSchema`
+    fn value(&self) -> Result<SegmentValue, StructPathError> {
+        match self.capture {
+            Capture::Value(_, segment_value_schema) | Capture::QueryValue(_, segment_value_schema) => {
+                segment_value_schema.parse(&self.decoded()?)
             },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+            Capture::Repeated(query, segment_value_schema) => {
+                let mut rest = query;
+                let mut values = vec![];
+                while let Some(raw_value) = next_query_value(&mut rest, &segment_value_schema.name) {
+                    values.push(segment_value_schema.parse(&decode_query_component(raw_value)?)?);
+                }
+                Ok(SegmentValue::Sequence(values))
+            },
+            Capture::Tail(rest) => {
+                let mut tail = vec![];
+                for raw_segment in rest.iter().flat_map(|rest| rest.split('/')) {
+                    tail.push(SegmentValue::String(percent_decode_str(raw_segment).decode_utf8()?.into_owned()));
+                }
+                Ok(SegmentValue::Sequence(tail))
+            },
+            Capture::Parsed(segment_value, _) => Ok(segment_value.clone()),
+        }
     }
+}
 
-    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::U8(value) => (DeserializerState::Map, visitor.visit_u8(*value)),
-                _ => return Err(StructPathError::ExpectedType("u8".to_owned(), segment_value.clone())),
+impl<'de, 's> serde::de::Deserializer<'de> for SegmentDeserializer<'de, 's> {
+    type Error = StructPathError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.capture {
+            Capture::Value(_, segment_value_schema) | Capture::QueryValue(_, segment_value_schema) => match segment_value_schema.segment_type {
+                SegmentType::String | SegmentType::Enum | SegmentType::Choice(_) | SegmentType::Custom(_) => self.deserialize_str(visitor),
+                _ => visit_segment_value(self.value()?, visitor),
             },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+            Capture::Tail(_) | Capture::Repeated(..) | Capture::Parsed(SegmentValue::Sequence(_), _) => self.deserialize_seq(visitor),
+            Capture::Parsed(SegmentValue::String(_), _) | Capture::Parsed(SegmentValue::Custom{..}, _) => self.deserialize_str(visitor),
+            Capture::Parsed(segment_value, _) => visit_segment_value(segment_value.clone(), visitor),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::Bool(value) => visitor.visit_bool(value),
+            segment_value => Err(StructPathError::ExpectedType("bool".to_owned(), segment_value)),
+        }
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::I8(value) => visitor.visit_i8(value),
+            segment_value => Err(StructPathError::ExpectedType("i8".to_owned(), segment_value)),
+        }
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::I16(value) => visitor.visit_i16(value),
+            segment_value => Err(StructPathError::ExpectedType("i16".to_owned(), segment_value)),
+        }
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::I32(value) => visitor.visit_i32(value),
+            segment_value => Err(StructPathError::ExpectedType("i32".to_owned(), segment_value)),
+        }
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::I64(value) => visitor.visit_i64(value),
+            segment_value => Err(StructPathError::ExpectedType("i64".to_owned(), segment_value)),
+        }
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::I128(value) => visitor.visit_i128(value),
+            segment_value => Err(StructPathError::ExpectedType("i128".to_owned(), segment_value)),
+        }
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::U8(value) => visitor.visit_u8(value),
+            segment_value => Err(StructPathError::ExpectedType("u8".to_owned(), segment_value)),
+        }
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::U64(value) => (DeserializerState::Map, visitor.visit_u64(*value)),
-                _ => return Err(StructPathError::ExpectedType("u64".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+        match self.value()? {
+            SegmentValue::U16(value) => visitor.visit_u16(value),
+            segment_value => Err(StructPathError::ExpectedType("u16".to_owned(), segment_value)),
+        }
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::U64(value) => (DeserializerState::Map, visitor.visit_u64(*value)),
-                _ => return Err(StructPathError::ExpectedType("u64".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+        match self.value()? {
+            SegmentValue::U32(value) => visitor.visit_u32(value),
+            segment_value => Err(StructPathError::ExpectedType("u32".to_owned(), segment_value)),
+        }
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::U64(value) => (DeserializerState::Map, visitor.visit_u64(*value)),
-                _ => return Err(StructPathError::ExpectedType("u64".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+        match self.value()? {
+            SegmentValue::U64(value) => visitor.visit_u64(value),
+            segment_value => Err(StructPathError::ExpectedType("u64".to_owned(), segment_value)),
+        }
     }
 
     fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::U128(value) => (DeserializerState::Map, visitor.visit_u128(*value)),
-                _ => return Err(StructPathError::ExpectedType("u128".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+        match self.value()? {
+            SegmentValue::U128(value) => visitor.visit_u128(value),
+            segment_value => Err(StructPathError::ExpectedType("u128".to_owned(), segment_value)),
+        }
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::F32(value) => (DeserializerState::Map, visitor.visit_f32(*value)),
-                _ => return Err(StructPathError::ExpectedType("f32".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+        match self.value()? {
+            SegmentValue::F32(value) => visitor.visit_f32(value),
+            segment_value => Err(StructPathError::ExpectedType("f32".to_owned(), segment_value)),
+        }
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::F64(value) => (DeserializerState::Map, visitor.visit_f64(*value)),
-                _ => return Err(StructPathError::ExpectedType("f64".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+        match self.value()? {
+            SegmentValue::F64(value) => visitor.visit_f64(value),
+            segment_value => Err(StructPathError::ExpectedType("f64".to_owned(), segment_value)),
+        }
     }
 
-    fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("char".to_owned()))
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.value()? {
+            SegmentValue::Char(value) => visitor.visit_char(value),
+            segment_value => Err(StructPathError::ExpectedType("char".to_owned(), segment_value)),
+        }
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        self.deserialize_string(visitor)
+        match self.capture {
+            Capture::Value(_, segment_value_schema) | Capture::QueryValue(_, segment_value_schema) => match segment_value_schema.segment_type {
+                SegmentType::String | SegmentType::Enum if segment_value_schema.constraint.is_none() => (),
+                // checks the value parses as its type and satisfies any constraint, so e.g. a
+                // `HashMap<String, String>` only gets valid values
+                _ => {
+                    self.value()?;
+                },
+            },
+            Capture::Tail(_) | Capture::Parsed(_, true) => (),
+            Capture::Parsed(SegmentValue::Sequence(_), false) | Capture::Repeated(..) => {
+                return Err(StructPathError::ExpectedType("String".to_owned(), self.value()?));
+            },
+            Capture::Parsed(..) => (),
+        }
+        match self.decoded()? {
+            Cow::Borrowed(value) => visitor.visit_borrowed_str(value),
+            Cow::Owned(value) => visitor.visit_string(value),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        let (next_state, result) = match &self.state {
-            DeserializerState::MapValue(segment_value) => match segment_value {
-                SegmentValue::String(value) => (DeserializerState::Map, visitor.visit_string(value.clone())),
-                _ => return Err(StructPathError::ExpectedType("String".to_owned(), segment_value.clone())),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_owned(),
-                got: self.state.clone(),
-            })
-        };
-        self.state = next_state;
-        result
+        self.deserialize_str(visitor)
     }
 
     fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
@@ -575,8 +1610,9 @@ impl <'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer {
         Err(StructPathError::NotSupported("bytes_buf".to_owned()))
     }
 
-    fn deserialize_option<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("Option".to_owned()))
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        // absent optional segments are never captured, so serde fills those in with `None`
+        visitor.visit_some(self)
     }
 
     fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
@@ -587,12 +1623,18 @@ impl <'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer {
         Err(StructPathError::NotSupported("unit struct".to_owned()))
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("newtype struct".to_owned()))
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        // newtypes are transparent, e.g. `struct UserId(u64)` is parsed just like a `u64`
+        visitor.visit_newtype_struct(self)
     }
 
-    fn deserialize_seq<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("sequence".to_owned()))
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        match self.capture {
+            Capture::Tail(rest) => visitor.visit_seq(TailAccess{rest}),
+            Capture::Repeated(rest, segment_value_schema) => visitor.visit_seq(QueryAccess{rest, segment_value_schema}),
+            Capture::Parsed(SegmentValue::Sequence(values), _) => visitor.visit_seq(ParsedAccess{values: values.iter()}),
+            _ => Err(StructPathError::ExpectedType("sequence".to_owned(), self.value()?)),
+        }
     }
 
     fn deserialize_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
@@ -603,311 +1645,311 @@ impl <'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer {
         Err(StructPathError::NotSupported("tuple struct".to_owned()))
     }
 
-    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        self.state = match self.state {
-            DeserializerState::Start => DeserializerState::Map,
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "Start".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        visitor.visit_map(self)
+    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        Err(StructPathError::NotSupported("map".to_owned()))
     }
 
-    fn deserialize_struct<V>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        self.deserialize_map(visitor)
+    fn deserialize_struct<V>(self, _name: &'static str, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        Err(StructPathError::NotSupported("struct".to_owned()))
     }
 
-    fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("enum".to_owned()))
+    fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        visitor.visit_enum(self)
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        match &self.state {
-            DeserializerState::MapKey(key) => visitor.visit_string(key.clone()),
-            _ => Err(StructPathError::InvalidDeserializerState{
-                expected: "MapKey".to_string(),
-                got: self.state.clone(),
-            }),
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        if let Some(name) = self.unused_name {
+            return Err(StructPathError::UnusedValue(name.to_owned()));
         }
+        // the path must still match the schema, even if nothing uses this value
+        self.value()?;
+        visitor.visit_unit()
     }
+}
+
+impl<'de, 's> serde::de::EnumAccess<'de> for SegmentDeserializer<'de, 's> {
+    type Error = StructPathError;
+    type Variant = UnitVariantAccess;
 
-    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
-        Err(StructPathError::NotSupported("deserialize_ignored_any".to_owned()))
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error> where V: serde::de::DeserializeSeed<'de> {
+        Ok((seed.deserialize(self)?, UnitVariantAccess))
     }
 }
 
-impl<'de, 'a> serde::de::MapAccess<'de> for &'a mut Deserializer {
+/// Only unit variants can be parsed from a single segment
+struct UnitVariantAccess;
+
+impl<'de> serde::de::VariantAccess<'de> for UnitVariantAccess {
     type Error = StructPathError;
 
-    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> where K: serde::de::DeserializeSeed<'de> {
-        let (has_next_key, next_state) = match self.state {
-            DeserializerState::Map => {
-                match self.generic_parsed_path.keys().nth(0) {
-                    Some(key) => (true, DeserializerState::MapKey(key.clone())),
-                    None => (false, DeserializerState::End),
-                }
-            }
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "Map".to_string(),
-                got: self.state.clone(),
-            }),
-        };
-        self.state = next_state;
-        if !has_next_key {
-            return Ok(None)
-        }
-        match &self.state {
-            DeserializerState::MapKey(_) => seed.deserialize(&mut **self).map(Some),
-            _ => Err(StructPathError::InvalidDeserializerState{
-                expected: "MapKey".to_string(),
-                got: self.state.clone(),
-            }),
-        }
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
     }
 
-    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>  where V: serde::de::DeserializeSeed<'de> {
-        let value = match &self.state {
-            DeserializerState::MapKey(key) => match self.generic_parsed_path.remove(key) {
-                Some(value) => value.clone(),
-                None => return Err(StructPathError::Impossible),
-            },
-            _ => return Err(StructPathError::InvalidDeserializerState{
-                expected: "MapValue".to_string(),
-                got: self.state.clone(),
-            }),
-        };
-        self.state = DeserializerState::MapValue(value.clone());
-        seed.deserialize(&mut **self)
+    fn newtype_variant_seed<T>(self, _seed: T) -> Result<T::Value, Self::Error> where T: serde::de::DeserializeSeed<'de> {
+        Err(StructPathError::NotSupported("newtype variant".to_owned()))
     }
-}
 
-/// Parse a particular path using a `Schema`
-///
-/// Typical errors will include when the Schema doesn't match T's structure.
-pub fn parse_path<'a, S, T>(path: S, schema: &Schema) -> Result<T, StructPathError> where S: Into<String>, T: serde::Deserialize<'a> {
-    let generic_parsed_path_value = parse_path_generic(path.into(), schema)?;
-    let mut deserializer = Deserializer{
-        generic_parsed_path: generic_parsed_path_value,
-        state: DeserializerState::Start,
-    };
-    T::deserialize(&mut deserializer)
-}
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        Err(StructPathError::NotSupported("tuple variant".to_owned()))
+    }
 
-/// Internal state used by the Serializer, typically only used for debugging.
-#[derive(Debug, Clone)]
-pub enum SerializerState {
-    Start, // starting, expecting a struct
-    StructKey,  // in a struct, about to parse next key
-    StructValue(String),  // about to serialize a struct value, this holds the key
-    End,  // ending, not expecting any other states
+    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> where V: Visitor<'de> {
+        Err(StructPathError::NotSupported("struct variant".to_owned()))
+    }
 }
 
-struct Serializer{
-    serialized_values: HashMap<String, String>,
-    state: SerializerState,
+/// The schema of each segment in a tail
+static TAIL_SEGMENT: SegmentValueSchema = SegmentValueSchema{
+    name: String::new(),
+    segment_type: SegmentType::String,
+    constraint: None,
+};
+
+/// Hands out the segments of a tail one by one
+struct TailAccess<'de> {
+    rest: Option<&'de str>,
 }
 
-impl<'a> serde::ser::Serializer for &'a mut Serializer {
-    type Ok = ();
+impl<'de> serde::de::SeqAccess<'de> for TailAccess<'de> {
     type Error = StructPathError;
 
-    type SerializeSeq = Self;
-    type SerializeTuple = Self;
-    type SerializeTupleStruct = Self;
-    type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
-    type SerializeStruct = Self;
-    type SerializeStructVariant = Self;
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> where T: serde::de::DeserializeSeed<'de> {
+        let rest = match self.rest {
+            Some(rest) => rest,
+            None => return Ok(None),
+        };
+        let raw_segment = match rest.find('/') {
+            Some(index) => {
+                self.rest = Some(&rest[index + 1..]);
+                &rest[..index]
+            },
+            None => {
+                self.rest = None;
+                rest
+            },
+        };
+        seed.deserialize(SegmentDeserializer{capture: Capture::Value(raw_segment, &TAIL_SEGMENT), unused_name: None}).map(Some)
+    }
+}
+
+/// Hands out the values of a repeated query parameter one by one
+struct QueryAccess<'de, 's> {
+    rest: Option<&'de str>,
+    segment_value_schema: &'s SegmentValueSchema,
+}
+
+impl<'de, 's> serde::de::SeqAccess<'de> for QueryAccess<'de, 's> {
+    type Error = StructPathError;
 
-    fn serialize_bool(self, _v: bool) -> Result<(), StructPathError> {
-        Err(StructPathError::NotSupported("bool".to_owned()))
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> where T: serde::de::DeserializeSeed<'de> {
+        match next_query_value(&mut self.rest, &self.segment_value_schema.name) {
+            Some(raw_value) => seed.deserialize(SegmentDeserializer{capture: Capture::QueryValue(raw_value, self.segment_value_schema), unused_name: None}).map(Some),
+            None => Ok(None),
+        }
     }
+}
 
-    fn serialize_i8(self, v: i8) -> Result<(), StructPathError> {
+/// Hands out the elements of an already parsed sequence one by one
+struct ParsedAccess<'de> {
+    values: std::slice::Iter<'de, SegmentValue>,
+}
+
+impl<'de> serde::de::SeqAccess<'de> for ParsedAccess<'de> {
+    type Error = StructPathError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> where T: serde::de::DeserializeSeed<'de> {
+        match self.values.next() {
+            Some(segment_value) => seed.deserialize(SegmentDeserializer{capture: Capture::Parsed(segment_value, false), unused_name: None}).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Parse a particular path using a `Schema`
+///
+/// Typical errors will include when the Schema doesn't match T's structure.
+pub fn parse_path<S, T>(path: S, schema: &Schema) -> Result<T, StructPathError> where S: Into<String>, T: serde::de::DeserializeOwned {
+    parse_path_str(&path.into(), schema)
+}
+
+/// Parse a particular path using a `Schema`, borrowing from the path where possible
+///
+/// Unlike `parse_path`, `T` may contain `&str` fields borrowed from `path`, these only fail to
+/// parse if their segment needs percent-decoding.
+pub fn parse_path_str<'de, T>(path: &'de str, schema: &Schema) -> Result<T, StructPathError> where T: serde::Deserialize<'de> {
+    let captures = CaptureWalker::new(path, schema)?.captures()?;
+    let captures = captures.into_iter().map(|(name, capture)| (name, name, capture)).collect();
+    T::deserialize(CaptureMap::new(captures, schema.deny_unused_values))
+}
+
+/// Parse a path into an enum value, choosing `variant` and filling in its fields from the path
+pub(crate) fn parse_variant_path<'de, 's, T>(path: &'de str, schema: &'s Schema, variant: &'s str) -> Result<T, StructPathError> where T: serde::Deserialize<'de> {
+    let captures = CaptureWalker::new(path, schema)?.captures()?;
+    let captures = captures.into_iter().map(|(name, capture)| (name, name, capture)).collect();
+    T::deserialize(VariantDeserializer{variant, map: CaptureMap::new(captures, schema.deny_unused_values)})
+}
+
+/// Deserialize values already parsed from a path by `schema`, e.g. by `Router::at`
+pub(crate) fn deserialize_values<'de, T>(values: &'de HashMap<String, SegmentValue>, schema: &Schema) -> Result<T, StructPathError> where T: serde::Deserialize<'de> {
+    let captures = values.iter().map(|(name, segment_value)| {
+        let tail = matches!(schema.segments.last(), Some(SegmentSchema::Tail(tail)) if tail == name);
+        (name.as_str(), name.as_str(), Capture::Parsed(segment_value, tail))
+    }).collect();
+    T::deserialize(CaptureMap::new(captures, schema.deny_unused_values))
+}
+
+/// Internal state used by the Serializer, typically only used for debugging.
+#[derive(Debug, Clone)]
+pub enum SerializerState {
+    Start, // starting, expecting a struct or map
+    StructKey,  // in a struct or map, about to parse next key
+    MapKey,  // serializing a map key, which is used just like a struct field's name
+    StructValue(String),  // about to serialize a struct value, this holds the key
+    SequenceElement(String),  // serializing the elements of a sequence struct value, this holds the key
+    End,  // ending, not expecting any other states
+}
+
+/// A value collected by the Serializer, ready to be placed into a path
+enum SerializedValue {
+    Single(String),
+    Sequence(Vec<String>),
+}
+
+struct Serializer{
+    serialized_values: HashMap<String, SerializedValue>,
+    state: SerializerState,
+    /// The full names of the fields holding each nested struct or map being serialized
+    prefixes: Vec<String>,
+    /// The enum variant the parameters were serialized from, see `EnumRouter`
+    variant: Option<&'static str>,
+}
+
+impl Serializer {
+    /// A field's full name, prefixed with the fields it's nested in, e.g. `org.id`
+    fn field_name(&self, key: &str) -> String {
+        match self.prefixes.last() {
+            Some(prefix) => format!("{}.{}", prefix, key),
+            None => key.to_owned(),
+        }
+    }
+
+    /// Start serializing the fields of a struct or map, either the parameters or a nested value
+    fn start_fields(&mut self, kind: &str) -> Result<(), StructPathError> {
         self.state = match &self.state {
+            SerializerState::Start => SerializerState::StructKey,
             SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
+                self.prefixes.push(key.clone());
                 SerializerState::StructKey
             },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
+            _ => return Err(StructPathError::NotSupported(format!("{} in a sequence or map key", kind))),
         };
         Ok(())
     }
 
-    fn serialize_i16(self, v: i16) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
+    fn end_fields(&mut self) {
+        self.state = match self.prefixes.pop() {
+            Some(_) => SerializerState::StructKey,
+            None => SerializerState::End,
         };
-        Ok(())
     }
 
-    fn serialize_i32(self, v: i32) -> Result<(), StructPathError> {
+    fn push_value(&mut self, value: String) -> Result<(), StructPathError> {
         self.state = match &self.state {
+            SerializerState::MapKey => SerializerState::StructValue(self.field_name(&value)),
             SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
+                self.serialized_values.insert(key.clone(), SerializedValue::Single(value));
                 SerializerState::StructKey
             },
+            SerializerState::SequenceElement(key) => {
+                if let Some(SerializedValue::Sequence(values)) = self.serialized_values.get_mut(key) {
+                    values.push(value);
+                }
+                SerializerState::SequenceElement(key.clone())
+            },
             _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
+                expected: "MapKey, StructValue or SequenceElement".to_owned(),
                 got: self.state.clone(),
             }),
         };
         Ok(())
     }
+}
+
+impl serde::ser::Serializer for &mut Serializer {
+    type Ok = ();
+    type Error = StructPathError;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), StructPathError> {
+        self.push_value(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), StructPathError> {
+        self.push_value(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), StructPathError> {
+        self.push_value(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), StructPathError> {
+        self.push_value(v.to_string())
+    }
 
     fn serialize_i64(self, v: i64) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_i128(self, v: i128) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_u8(self, v: u8) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_u16(self, v: u16) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_u32(self, v: u32) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_u64(self, v: u64) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_u128(self, v: u128) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_f32(self, v: f32) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
     fn serialize_f64(self, v: f64) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_string());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_string())
     }
 
-    fn serialize_char(self, _v: char) -> Result<(), StructPathError> {
-        Err(StructPathError::NotSupported("char".to_owned()))
+    fn serialize_char(self, v: char) -> Result<(), StructPathError> {
+        self.push_value(v.to_string())
     }
 
     fn serialize_str(self, v: &str) -> Result<(), StructPathError> {
-        self.state = match &self.state {
-            SerializerState::StructValue(key) => {
-                self.serialized_values.insert(key.clone(), v.to_owned());
-                SerializerState::StructKey
-            },
-            _ => return Err(StructPathError::InvalidSerializerState{
-                expected: "StructValue".to_owned(),
-                got: self.state.clone(),
-            }),
-        };
-        Ok(())
+        self.push_value(v.to_owned())
     }
 
     fn serialize_bytes(self, _v: &[u8]) -> Result<(), StructPathError> {
@@ -915,11 +1957,18 @@ impl<'a> serde::ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_none(self) -> Result<(), StructPathError> {
-        Err(StructPathError::NotSupported("None".to_owned()))
+        self.state = match &self.state {
+            SerializerState::StructValue(_) => SerializerState::StructKey,
+            _ => return Err(StructPathError::InvalidSerializerState{
+                expected: "StructValue".to_owned(),
+                got: self.state.clone(),
+            }),
+        };
+        Ok(())
     }
 
-    fn serialize_some<T>(self, _value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
-        Err(StructPathError::NotSupported("Some".to_owned()))
+    fn serialize_some<T>(self, value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
+        value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<(), StructPathError> {
@@ -934,31 +1983,56 @@ impl<'a> serde::ser::Serializer for &'a mut Serializer {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<(), StructPathError> {
-        Err(StructPathError::NotSupported("unit variant".to_owned()))
+        match self.state {
+            // a unit variant of the parameters themselves, which has no values
+            SerializerState::Start => {
+                self.variant = Some(variant);
+                self.state = SerializerState::End;
+                Ok(())
+            },
+            _ => self.push_value(variant.to_owned()),
+        }
     }
 
     fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
-        _value: &T,
+        value: &T,
         ) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize, {
-        Err(StructPathError::NotSupported("newtype struct".to_owned()))
+        value.serialize(self)
     }
 
     fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
-        _value: &T,
+        variant: &'static str,
+        value: &T,
         ) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize, {
-        Err(StructPathError::NotSupported("newtype variant".to_owned()))
+        match self.state {
+            // the variant's value holds the parameters, e.g. `Route::Search(SearchParams)`
+            SerializerState::Start => {
+                self.variant = Some(variant);
+                value.serialize(self)
+            },
+            _ => Err(StructPathError::NotSupported("newtype variant".to_owned())),
+        }
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, StructPathError> {
-        Err(StructPathError::NotSupported("sequence".to_owned()))
+        self.state = match &self.state {
+            SerializerState::StructValue(key) => {
+                self.serialized_values.insert(key.clone(), SerializedValue::Sequence(vec![]));
+                SerializerState::SequenceElement(key.clone())
+            },
+            _ => return Err(StructPathError::InvalidSerializerState{
+                expected: "StructValue".to_owned(),
+                got: self.state.clone(),
+            }),
+        };
+        Ok(self)
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, StructPathError> {
@@ -984,8 +2058,8 @@ impl<'a> serde::ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, StructPathError> {
-        // TODO should probaby support this
-        Err(StructPathError::NotSupported("map".to_owned()))
+        self.start_fields("map")?;
+        Ok(self)
     }
 
     fn serialize_struct(
@@ -993,7 +2067,7 @@ impl<'a> serde::ser::Serializer for &'a mut Serializer {
         _name: &'static str,
         _len: usize,
         ) -> Result<Self::SerializeStruct, StructPathError> {
-        self.state = SerializerState::StructKey;
+        self.start_fields("struct")?;
         Ok(self)
     }
 
@@ -1001,28 +2075,42 @@ impl<'a> serde::ser::Serializer for &'a mut Serializer {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
         ) -> Result<Self::SerializeStructVariant, StructPathError> {
-        Err(StructPathError::NotSupported("struct variant".to_owned()))
+        match self.state {
+            SerializerState::Start => {
+                self.variant = Some(variant);
+                self.start_fields("struct variant")?;
+                Ok(self)
+            },
+            _ => Err(StructPathError::NotSupported("struct variant".to_owned())),
+        }
     }
 
 }
 
-impl<'a> serde::ser::SerializeSeq for &'a mut Serializer {
+impl serde::ser::SerializeSeq for &mut Serializer {
     type Ok = ();
     type Error = StructPathError;
 
-    fn serialize_element<T>(&mut self, _value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
-        Err(StructPathError::NotSupported("sequence".to_owned()))
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
+        value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<(), StructPathError> {
-        Err(StructPathError::NotSupported("sequence".to_owned()))
+        self.state = match self.state {
+            SerializerState::SequenceElement(_) => SerializerState::StructKey,
+            _ => return Err(StructPathError::InvalidSerializerState{
+                expected: "SequenceElement".to_owned(),
+                got: self.state.clone(),
+            }),
+        };
+        Ok(())
     }
 }
 
-impl<'a> serde::ser::SerializeTuple for &'a mut Serializer {
+impl serde::ser::SerializeTuple for &mut Serializer {
     type Ok = ();
     type Error = StructPathError;
 
@@ -1035,7 +2123,7 @@ impl<'a> serde::ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> serde::ser::SerializeTupleStruct for &'a mut Serializer {
+impl serde::ser::SerializeTupleStruct for &mut Serializer {
     type Ok = ();
     type Error = StructPathError;
 
@@ -1048,7 +2136,7 @@ impl<'a> serde::ser::SerializeTupleStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> serde::ser::SerializeTupleVariant for &'a mut Serializer {
+impl serde::ser::SerializeTupleVariant for &mut Serializer {
     type Ok = ();
     type Error = StructPathError;
 
@@ -1061,31 +2149,45 @@ impl<'a> serde::ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
-impl<'a> serde::ser::SerializeMap for &'a mut Serializer {
+impl serde::ser::SerializeMap for &mut Serializer {
     type Ok = ();
     type Error = StructPathError;
 
-    fn serialize_key<T>(&mut self, _key: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize, {
-        Err(StructPathError::NotSupported("map".to_owned()))
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize, {
+        self.state = match self.state {
+            SerializerState::StructKey => SerializerState::MapKey,
+            _ => return Err(StructPathError::InvalidSerializerState{
+                expected: "StructKey".to_owned(),
+                got: self.state.clone(),
+            }),
+        };
+        key.serialize(&mut **self)
     }
 
-    fn serialize_value<T>(&mut self, _value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
-        Err(StructPathError::NotSupported("map".to_owned()))
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
+        match self.state {
+            SerializerState::StructValue(_) => value.serialize(&mut **self),
+            _ => Err(StructPathError::InvalidSerializerState{
+                expected: "StructValue".to_owned(),
+                got: self.state.clone(),
+            }),
+        }
     }
 
     fn end(self) -> Result<(), StructPathError> {
-        Err(StructPathError::NotSupported("map".to_owned()))
+        self.end_fields();
+        Ok(())
     }
 }
 
-impl<'a> serde::ser::SerializeStruct for &'a mut Serializer {
+impl serde::ser::SerializeStruct for &mut Serializer {
     type Ok = ();
     type Error = StructPathError;
 
     fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
         self.state = match self.state {
             SerializerState::StructKey => {
-                SerializerState::StructValue(key.to_owned())
+                SerializerState::StructValue(self.field_name(key))
             },
             _ => return Err(StructPathError::InvalidSerializerState{
                 expected: "StructKey".to_owned(),
@@ -1097,41 +2199,176 @@ impl<'a> serde::ser::SerializeStruct for &'a mut Serializer {
     }
 
     fn end(self) -> Result<(), StructPathError> {
-        self.state = SerializerState::End;
+        self.end_fields();
         Ok(())
     }
 }
 
-impl<'a> serde::ser::SerializeStructVariant for &'a mut Serializer {
+impl serde::ser::SerializeStructVariant for &mut Serializer {
     type Ok = ();
     type Error = StructPathError;
 
-    fn serialize_field<T>(&mut self, _key: &'static str, _value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
-        Err(StructPathError::NotSupported("struct variant".to_owned()))
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), StructPathError> where T: ?Sized + serde::Serialize {
+        serde::ser::SerializeStruct::serialize_field(self, key, value)
     }
 
     fn end(self) -> Result<(), StructPathError> {
-        Err(StructPathError::NotSupported("struct variant".to_owned()))
+        serde::ser::SerializeStruct::end(self)
     }
 }
 
-/// Generate a string url path given parameters and a `Schema`
-pub fn generate_path<T>(parameters: &T, schema: &Schema) -> Result<String, StructPathError> where T: serde::Serialize {
-    let mut serializer = Serializer{
-        serialized_values: HashMap::new(),
-        state: SerializerState::Start,
+/// The text to generate for a serialized value, checking it is allowed by its `SegmentValueSchema`
+///
+/// Only choices, custom types and constrained values are checked, and booleans are written in
+/// their `BoolFormat`.
+fn generated_value<'v>(value: &'v str, segment_value_schema: &SegmentValueSchema) -> Result<Cow<'v, str>, StructPathError> {
+    let value = match (&segment_value_schema.segment_type, value) {
+        (SegmentType::Bool(bool_format), "true") => Cow::Owned(bool_format.true_text().to_owned()),
+        (SegmentType::Bool(bool_format), "false") => Cow::Owned(bool_format.false_text().to_owned()),
+        (_, value) => Cow::Borrowed(value),
+    };
+    let checked = matches!(segment_value_schema.segment_type, SegmentType::Choice(_) | SegmentType::Custom(_) | SegmentType::Bool(_));
+    if checked || segment_value_schema.constraint.is_some() {
+        segment_value_schema.parse(&value)?;
+    }
+    Ok(value)
+}
+
+/// Serialize parameters into the values to place into a path
+fn serialize_parameters<T>(parameters: &T) -> Result<Serializer, StructPathError> where T: serde::Serialize {
+    let mut serializer = Serializer{
+        serialized_values: HashMap::new(),
+        state: SerializerState::Start,
+        prefixes: vec![],
+        variant: None,
     };
     parameters.serialize(&mut serializer)?;
+    Ok(serializer)
+}
+
+/// Generate a string url path given parameters and a `Schema`
+pub fn generate_path<T>(parameters: &T, schema: &Schema) -> Result<String, StructPathError> where T: serde::Serialize {
+    generate_from_values(&serialize_parameters(parameters)?.serialized_values, schema)
+}
+
+/// Generate a path from an enum value, using the `Schema` `schema_for` gives for its variant
+pub(crate) fn generate_variant_path<'s, T, F>(parameters: &T, schema_for: F) -> Result<String, StructPathError>
+    where T: serde::Serialize, F: FnOnce(&str) -> Option<&'s Schema> {
+    let serializer = serialize_parameters(parameters)?;
+    let variant = serializer.variant.ok_or_else(|| StructPathError::NotSupported("parameters which aren't an enum".to_owned()))?;
+    let schema = schema_for(variant).ok_or_else(|| StructPathError::UnknownVariant(variant.to_owned()))?;
+    generate_from_values(&serializer.serialized_values, schema)
+}
+
+fn generate_from_values(serialized_values: &HashMap<String, SerializedValue>, schema: &Schema) -> Result<String, StructPathError> {
     let mut generated_path = String::new();
+    let mut missing_optional: Option<&String> = None;
     for segment_schema in &schema.segments {
         match segment_schema {
-            SegmentSchema::Literal(literal) => generated_path = format!("{}/{}", generated_path, literal),
-            SegmentSchema::Value(segment_value_schema) => match serializer.serialized_values.get(&segment_value_schema.name) {
-                Some(value) => generated_path = format!("{}/{}", generated_path, value),
+            SegmentSchema::Literal(literal) => {
+                generated_path = format!("{}/{}", generated_path, utf8_percent_encode(literal, schema.encode_set));
+            },
+            SegmentSchema::Value(segment_value_schema) => match serialized_values.get(&segment_value_schema.name) {
+                Some(SerializedValue::Single(value)) => {
+                    let value = generated_value(value, segment_value_schema)?;
+                    generated_path = format!("{}/{}", generated_path, utf8_percent_encode(&value, schema.encode_set));
+                },
+                Some(SerializedValue::Sequence(_)) => {
+                    return Err(StructPathError::NotSupported(format!("sequence value for {:?}", segment_value_schema.name)));
+                },
                 None => return Err(StructPathError::MissingField(segment_value_schema.name.clone())),
-            }
+            },
+            SegmentSchema::Optional(segment_value_schema) => match (serialized_values.get(&segment_value_schema.name), missing_optional) {
+                // a present optional value can't be generated once an earlier one has been left out
+                (Some(_), Some(missing_name)) => return Err(StructPathError::MissingField(missing_name.clone())),
+                (Some(SerializedValue::Single(value)), None) => {
+                    let value = generated_value(value, segment_value_schema)?;
+                    generated_path = format!("{}/{}", generated_path, utf8_percent_encode(&value, schema.encode_set));
+                },
+                (Some(SerializedValue::Sequence(_)), None) => {
+                    return Err(StructPathError::NotSupported(format!("sequence value for {:?}", segment_value_schema.name)));
+                },
+                (None, _) => missing_optional = missing_optional.or(Some(&segment_value_schema.name)),
+            },
+            SegmentSchema::Pattern(parts) => {
+                let mut segment = String::new();
+                let mut ranges = vec![];
+                for part in parts {
+                    match part {
+                        PatternPart::Literal(literal) => segment.extend(utf8_percent_encode(literal, schema.encode_set)),
+                        PatternPart::Value(segment_value_schema) => match serialized_values.get(&segment_value_schema.name) {
+                            Some(SerializedValue::Single(value)) => {
+                                let value = generated_value(value, segment_value_schema)?;
+                                let start = segment.len();
+                                segment.extend(utf8_percent_encode(&value, schema.encode_set));
+                                ranges.push(start..segment.len());
+                            },
+                            Some(SerializedValue::Sequence(_)) => {
+                                return Err(StructPathError::NotSupported(format!("sequence value for {:?}", segment_value_schema.name)));
+                            },
+                            None => return Err(StructPathError::MissingField(segment_value_schema.name.clone())),
+                        },
+                    }
+                }
+                // e.g. `<name>.<ext>` can't generate a name and an extension which both contain '.'
+                let parsed_ranges: Vec<_> = pattern::split_pattern(parts, &segment)?.into_iter().map(|(_, range)| range).collect();
+                if parsed_ranges != ranges {
+                    return Err(StructPathError::AmbiguousPattern(segment));
+                }
+                generated_path = format!("{}/{}", generated_path, segment);
+            },
+            SegmentSchema::Tail(name) => match serialized_values.get(name) {
+                Some(SerializedValue::Single(value)) => for segment in value.split("/") {
+                    generated_path = format!("{}/{}", generated_path, utf8_percent_encode(segment, schema.encode_set));
+                },
+                Some(SerializedValue::Sequence(values)) => for segment in values {
+                    generated_path = format!("{}/{}", generated_path, utf8_percent_encode(segment, schema.encode_set));
+                },
+                None => return Err(StructPathError::MissingField(name.clone())),
+            },
         }
     }
+    let mut query = vec![];
+    for query_param_schema in &schema.query {
+        let segment_value_schema = query_param_schema.value_schema();
+        let name = &segment_value_schema.name;
+        let encoded_name = utf8_percent_encode(name, QUERY_ENCODE_SET);
+        match (query_param_schema, serialized_values.get(name)) {
+            (_, Some(SerializedValue::Single(value))) => {
+                let value = generated_value(value, segment_value_schema)?;
+                query.push(format!("{}={}", encoded_name, utf8_percent_encode(&value, QUERY_ENCODE_SET)));
+            },
+            (QueryParamSchema::Repeated(_), Some(SerializedValue::Sequence(values))) => for value in values {
+                let value = generated_value(value, segment_value_schema)?;
+                query.push(format!("{}={}", encoded_name, utf8_percent_encode(&value, QUERY_ENCODE_SET)));
+            },
+            (_, Some(SerializedValue::Sequence(_))) => {
+                return Err(StructPathError::NotSupported(format!("sequence value for {:?}", name)));
+            },
+            (QueryParamSchema::Required(_), None) => return Err(StructPathError::MissingField(name.clone())),
+            (_, None) => (),
+        }
+    }
+    let used: HashSet<&str> = schema.fields().into_iter().map(|(name, _)| name).collect();
+    let mut unused: Vec<_> = serialized_values.iter().filter(|(name, _)| !used.contains(name.as_str())).collect();
+    unused.sort_by_key(|(name, _)| *name);
+    for (name, value) in unused {
+        match schema.unused_keys {
+            UnusedKeys::Ignore => continue,
+            UnusedKeys::Deny => return Err(StructPathError::UnknownParameter(name.clone())),
+            UnusedKeys::Query => (),
+        }
+        let encoded_name = utf8_percent_encode(name, QUERY_ENCODE_SET);
+        match value {
+            SerializedValue::Single(value) => query.push(format!("{}={}", encoded_name, utf8_percent_encode(value, QUERY_ENCODE_SET))),
+            SerializedValue::Sequence(values) => for value in values {
+                query.push(format!("{}={}", encoded_name, utf8_percent_encode(value, QUERY_ENCODE_SET)));
+            },
+        }
+    }
+    if !query.is_empty() {
+        generated_path = format!("{}?{}", generated_path, query.join("&"));
+    }
     Ok(generated_path)
 }
 
@@ -1139,6 +2376,7 @@ pub fn generate_path<T>(parameters: &T, schema: &Schema) -> Result<String, Struc
 mod tests {
     use super::*;
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
     #[test]
     fn test_parse_path_generic() {
         assert_eq!(
@@ -1150,13 +2388,16 @@ mod tests {
                         SegmentSchema::Value(SegmentValueSchema{
                             name: "foo".to_owned(),
                             segment_type: SegmentType::U64,
+                            constraint: None,
                         }),
                         SegmentSchema::Literal("bar".to_owned()),
                         SegmentSchema::Value(SegmentValueSchema{
                             name: "bar".to_owned(),
                             segment_type: SegmentType::String,
+                            constraint: None,
                         }),
                     ],
+                    ..Schema::new()
                 }
             ).unwrap(),
             {
@@ -1179,8 +2420,10 @@ mod tests {
                         SegmentSchema::Value(SegmentValueSchema{
                             name: "foo".to_owned(),
                             segment_type: SegmentType::F64,
+                            constraint: None,
                         }),
                     ],
+                    ..Schema::new()
                 },
                 ).unwrap(),
             {
@@ -1202,8 +2445,10 @@ mod tests {
                         SegmentSchema::Value(SegmentValueSchema{
                             name: "foo".to_owned(),
                             segment_type: SegmentType::I128,
+                            constraint: None,
                         }),
                     ],
+                    ..Schema::new()
                 },
                 ).unwrap(),
             {
@@ -1229,13 +2474,16 @@ mod tests {
                         SegmentSchema::Value(SegmentValueSchema{
                             name: "foo".to_owned(),
                             segment_type: SegmentType::U64,
+                            constraint: None,
                         }),
                         SegmentSchema::Literal("bar".to_owned()),
                         SegmentSchema::Value(SegmentValueSchema{
                             name: "bar".to_owned(),
                             segment_type: SegmentType::String,
+                            constraint: None,
                         }),
                     ],
+                    ..Schema::new()
                 },
             );
     }
@@ -1250,13 +2498,16 @@ mod tests {
                     SegmentSchema::Value(SegmentValueSchema{
                         name: "foo_id".to_owned(),
                         segment_type: SegmentType::U128,
+                        constraint: None,
                     }),
                     SegmentSchema::Literal("bar".to_owned()),
                     SegmentSchema::Value(SegmentValueSchema{
                         name: "bar_thing".to_owned(),
                         segment_type: SegmentType::String,
+                        constraint: None,
                     }),
                 ],
+                ..Schema::new()
             }
             );
     }
@@ -1271,8 +2522,10 @@ mod tests {
                     SegmentSchema::Value(SegmentValueSchema{
                         name: "bar".to_owned(),
                         segment_type: SegmentType::String,
+                        constraint: None,
                     }),
                 ],
+                ..Schema::new()
             }
             );
     }
@@ -1294,13 +2547,16 @@ mod tests {
                     SegmentSchema::Value(SegmentValueSchema{
                         name: "foo".to_owned(),
                         segment_type: SegmentType::U64,
+                        constraint: None,
                     }),
                     SegmentSchema::Literal("bar".to_owned()),
                     SegmentSchema::Value(SegmentValueSchema{
                         name: "bar".to_owned(),
                         segment_type: SegmentType::String,
+                        constraint: None,
                     }),
                 ],
+                ..Schema::new()
             }
         ).unwrap();
         assert_eq!(value, Value{foo: 1, bar: "thing".to_owned()});
@@ -1321,8 +2577,10 @@ mod tests {
                     SegmentSchema::Value(SegmentValueSchema{
                         name: "foo".to_owned(),
                         segment_type: SegmentType::I128,
+                        constraint: None,
                     }),
                 ],
+                ..Schema::new()
             },
             ).unwrap();
         assert_eq!(value, Value{foo: -1});
@@ -1343,8 +2601,10 @@ mod tests {
                     SegmentSchema::Value(SegmentValueSchema{
                         name: "foo".to_owned(),
                         segment_type: SegmentType::F64,
+                        constraint: None,
                     }),
                 ],
+                ..Schema::new()
             },
             ).unwrap();
         assert_eq!(value, Value{foo: 1.2});
@@ -1393,4 +2653,933 @@ mod tests {
         assert_eq!(path_schema.generate(&parameters).unwrap(), test_path);
 
     }
+
+    #[test]
+    fn test_parse_path_too_many_segments() {
+        let schema = Schema::path("/foo/<foo:u64>/bar/<bar>").unwrap();
+        match parse_path_generic("/foo/1/bar/x/extra/junk".to_owned(), &schema) {
+            Err(StructPathError::TooManySegments{expected, got}) => assert_eq!((expected, got), (4, 6)),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_path_too_few_segments() {
+        let schema = Schema::path("/foo/<foo:u64>/bar/<bar>").unwrap();
+        match parse_path_generic("/foo".to_owned(), &schema) {
+            Err(StructPathError::TooFewSegments{expected, got}) => assert_eq!((expected, got), (4, 1)),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_path_prefix_mode() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Parameters{
+            foo: u64,
+            bar: String,
+        }
+
+        let schema = Schema::path("/foo/<foo:u64>/bar/<bar>").unwrap().match_mode(MatchMode::Prefix);
+        let parameters: Parameters = schema.parse("/foo/1/bar/thing/extra/junk").unwrap();
+        assert_eq!(parameters, Parameters{foo: 1, bar: "thing".to_owned()});
+        assert!(schema.parse::<_, Parameters>("/foo/1").is_err());
+    }
+
+    #[test]
+    fn test_parse_path_percent_decoding() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Parameters{
+            foo: u64,
+            bar: String,
+        }
+
+        let schema = Schema::path("/caf\u{e9}/<foo:u64>/<bar>").unwrap();
+        let parameters: Parameters = schema.parse("/caf%C3%A9/%31/a%2Fb%3Fc%23d%20e").unwrap();
+        assert_eq!(parameters, Parameters{foo: 1, bar: "a/b?c#d e".to_owned()});
+    }
+
+    #[test]
+    fn test_parse_path_invalid_utf8() {
+        let schema = Schema::path("/<bar>").unwrap();
+        match parse_path_generic("/%FF".to_owned(), &schema) {
+            Err(StructPathError::Utf8Error(_)) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_generate_path_percent_encoding() {
+        #[derive(Serialize, PartialEq, Debug)]
+        struct Parameters{
+            bar: String,
+        }
+
+        let schema = Schema::path("/caf\u{e9}/<bar>").unwrap();
+        let parameters = Parameters{bar: "a/b?c#d e\u{fc}:@".to_owned()};
+        assert_eq!(schema.generate(&parameters).unwrap(), "/caf%C3%A9/a%2Fb%3Fc%23d%20e%C3%BC:@");
+
+        let schema = schema.encode_set(NON_ALPHANUMERIC);
+        assert_eq!(schema.generate(&parameters).unwrap(), "/caf%C3%A9/a%2Fb%3Fc%23d%20e%C3%BC%3A%40");
+    }
+
+    #[test]
+    fn test_roundtrip_percent_encoding() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            slug: String,
+        }
+
+        let schema = Schema::path("/posts/<slug>").unwrap();
+        for slug in &["hello world", "50%/off?", "#1 \u{2603} r\u{e9}sum\u{e9}", "a+b&c=d"] {
+            let parameters = Parameters{slug: (*slug).to_owned()};
+            let path = schema.generate(&parameters).unwrap();
+            assert_eq!(schema.parse::<_, Parameters>(path).unwrap(), parameters);
+        }
+    }
+
+    #[test]
+    fn test_schema_path_tail() {
+        assert_eq!(
+            Schema::path("/repo/<name>/blob/<path..>").unwrap(),
+            Schema::new()
+                .literal("repo")
+                .value("name", SegmentType::String)
+                .literal("blob")
+                .tail("path"),
+            );
+        assert!(Schema::path("/repo/<path..>/blob").is_err());
+    }
+
+    #[test]
+    fn test_parse_path_generic_tail() {
+        let schema = Schema::path("/repo/<name>/blob/<path..>").unwrap();
+        assert_eq!(
+            parse_path_generic("/repo/structpath/blob/src/lib%2Ers".to_owned(), &schema).unwrap(),
+            {
+                let mut map = HashMap::new();
+                map.insert("name".to_owned(), SegmentValue::String("structpath".to_owned()));
+                map.insert("path".to_owned(), SegmentValue::Sequence(vec![
+                    SegmentValue::String("src".to_owned()),
+                    SegmentValue::String("lib.rs".to_owned()),
+                ]));
+                map
+            },
+            );
+        match parse_path_generic("/repo/structpath".to_owned(), &schema) {
+            Err(StructPathError::TooFewSegments{expected, got}) => assert_eq!((expected, got), (3, 2)),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_tail_string() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            name: String,
+            path: String,
+        }
+
+        let schema = Schema::path("/repo/<name>/blob/<path..>").unwrap();
+        let parameters: Parameters = schema.parse("/repo/structpath/blob/src/my%20lib.rs").unwrap();
+        assert_eq!(parameters, Parameters{name: "structpath".to_owned(), path: "src/my lib.rs".to_owned()});
+        assert_eq!(schema.generate(&parameters).unwrap(), "/repo/structpath/blob/src/my%20lib.rs");
+
+        let parameters: Parameters = schema.parse("/repo/structpath/blob").unwrap();
+        assert_eq!(parameters, Parameters{name: "structpath".to_owned(), path: "".to_owned()});
+    }
+
+    #[test]
+    fn test_roundtrip_tail_vec() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            name: String,
+            path: Vec<String>,
+        }
+
+        let schema = Schema::path("/repo/<name>/blob/<path..>").unwrap();
+        let parameters: Parameters = schema.parse("/repo/structpath/blob/src/a%2Fb").unwrap();
+        assert_eq!(parameters, Parameters{name: "structpath".to_owned(), path: vec!["src".to_owned(), "a/b".to_owned()]});
+        assert_eq!(schema.generate(&parameters).unwrap(), "/repo/structpath/blob/src/a%2Fb");
+
+        let parameters = Parameters{name: "structpath".to_owned(), path: vec![]};
+        assert_eq!(schema.generate(&parameters).unwrap(), "/repo/structpath/blob");
+        assert_eq!(schema.parse::<_, Parameters>("/repo/structpath/blob").unwrap(), parameters);
+    }
+
+    #[test]
+    fn test_schema_path_optional() {
+        assert_eq!(
+            Schema::path("/items/<page:u32?>/<sort?>").unwrap(),
+            Schema::new()
+                .literal("items")
+                .optional_value("page", SegmentType::U32)
+                .optional_value("sort", SegmentType::String),
+            );
+        assert!(Schema::path("/items/<page:u32?>/<sort>").is_err());
+        assert!(Schema::path("/items/<page:u32?>/sort").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_optional() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            page: Option<u64>,
+            sort: Option<String>,
+        }
+
+        let schema = Schema::path("/items/<page:u64?>/<sort?>").unwrap();
+        for (path, parameters) in [
+            ("/items", Parameters{page: None, sort: None}),
+            ("/items/2", Parameters{page: Some(2), sort: None}),
+            ("/items/2/name", Parameters{page: Some(2), sort: Some("name".to_owned())}),
+        ] {
+            assert_eq!(schema.parse::<_, Parameters>(path).unwrap(), parameters);
+            assert_eq!(schema.generate(&parameters).unwrap(), path);
+        }
+        assert!(schema.parse::<_, Parameters>("/items/2/name/extra").is_err());
+        assert!(schema.generate(&Parameters{page: None, sort: Some("name".to_owned())}).is_err());
+    }
+
+    #[test]
+    fn test_parse_str_borrowed() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Parameters<'a>{
+            name: &'a str,
+            #[serde(borrow)]
+            sort: Cow<'a, str>,
+            id: u32,
+            version: u16,
+            path: Vec<&'a str>,
+        }
+
+        let schema = Schema::path("/repo/<name>/<sort>/<id:u32>/<version:u16>/<path..>").unwrap();
+        let parameters: Parameters = schema.parse_str("/repo/structpath/by%20name/12/3/src/lib.rs").unwrap();
+        assert_eq!(parameters, Parameters{
+            name: "structpath",
+            sort: Cow::Owned("by name".to_owned()),
+            id: 12,
+            version: 3,
+            path: vec!["src", "lib.rs"],
+        });
+        assert!(matches!(
+            schema.parse_str::<Parameters>("/repo/structpath/name/12/3/src/lib.rs").unwrap().sort,
+            Cow::Borrowed("name"),
+            ));
+        // a &str can't hold a value which needed decoding
+        assert!(schema.parse_str::<Parameters>("/repo/struct%20path/name/12/3/src").is_err());
+        assert!(matches!(
+            schema.parse_str::<Parameters>("/repo/structpath/name/12"),
+            Err(StructPathError::TooFewSegments{expected: 5, got: 4}),
+            ));
+        assert!(matches!(
+            schema.parse_str::<Parameters>("/repo/structpath/name/12/x/src"),
+            Err(StructPathError::ParseIntError(_)),
+            ));
+    }
+
+    #[test]
+    fn test_schema_path_query() {
+        assert_eq!(
+            Schema::path("/search/<term>?<page:u32>&<sort?>&<tag..>").unwrap(),
+            Schema::new()
+                .literal("search")
+                .value("term", SegmentType::String)
+                .query_param("page", SegmentType::U32)
+                .optional_query_param("sort", SegmentType::String)
+                .repeated_query_param("tag", SegmentType::String),
+            );
+        assert!(Schema::path("/search?page").is_err());
+        assert!(Schema::path("/search?<page:u1000>").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_query() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            term: String,
+            page: u32,
+            sort: Option<String>,
+            tag: Vec<String>,
+        }
+
+        let schema = Schema::path("/search/<term>?<page:u32>&<sort?>&<tag..>").unwrap();
+        for (path, parameters) in [
+            ("/search/rust?page=2", Parameters{term: "rust".to_owned(), page: 2, sort: None, tag: vec![]}),
+            ("/search/rust?page=2&sort=name&tag=web&tag=a%26b", Parameters{
+                term: "rust".to_owned(),
+                page: 2,
+                sort: Some("name".to_owned()),
+                tag: vec!["web".to_owned(), "a&b".to_owned()],
+            }),
+        ] {
+            assert_eq!(schema.parse::<_, Parameters>(path).unwrap(), parameters);
+            assert_eq!(schema.generate(&parameters).unwrap(), path);
+        }
+        assert_eq!(
+            schema.parse::<_, Parameters>("/search/rust?tag=a+b&unknown=1&page=3&tag=c%20d").unwrap(),
+            Parameters{term: "rust".to_owned(), page: 3, sort: None, tag: vec!["a b".to_owned(), "c d".to_owned()]},
+            );
+        assert!(matches!(
+            schema.parse::<_, Parameters>("/search/rust?sort=name"),
+            Err(StructPathError::MissingField(name)) if name == "page",
+            ));
+        assert!(matches!(
+            schema.parse::<_, Parameters>("/search/rust?page=1&page=2"),
+            Err(StructPathError::DuplicateQueryParam(name)) if name == "page",
+            ));
+        assert!(schema.parse::<_, Parameters>("/search/rust?page=x").is_err());
+    }
+
+    #[test]
+    fn test_parse_query_borrowed() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Parameters<'a>{
+            id: u64,
+            sort: Option<&'a str>,
+        }
+
+        let schema = Schema::path("/items/<id:u64>?<sort?>").unwrap();
+        assert_eq!(schema.parse_str::<Parameters>("/items/1?sort=name").unwrap(), Parameters{id: 1, sort: Some("name")});
+        assert_eq!(schema.parse_str::<Parameters>("/items/1").unwrap(), Parameters{id: 1, sort: None});
+        assert_eq!(
+            parse_path_generic("/items/1?sort=a+b".to_owned(), &schema).unwrap(),
+            {
+                let mut map = HashMap::new();
+                map.insert("id".to_owned(), SegmentValue::U64(1));
+                map.insert("sort".to_owned(), SegmentValue::String("a b".to_owned()));
+                map
+            },
+            );
+        // the query string is split off even when the schema doesn't describe one
+        let schema = Schema::path("/items/<id:u64>").unwrap();
+        assert_eq!(parse_path_generic("/items/1?x=y".to_owned(), &schema).unwrap().len(), 1);
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    #[serde(rename_all = "snake_case")]
+    enum Status {
+        Open,
+        Closed,
+        #[serde(rename = "wont-fix")]
+        WontFix,
+    }
+
+    #[test]
+    fn test_schema_path_enum() {
+        assert_eq!(
+            Schema::path("/issues/<status:enum>/<sort:new|old>").unwrap(),
+            Schema::new()
+                .literal("issues")
+                .value("status", SegmentType::Enum)
+                .value("sort", SegmentType::Choice(vec!["new".to_owned(), "old".to_owned()])),
+            );
+    }
+
+    #[test]
+    fn test_roundtrip_enum() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            status: Status,
+            sort: String,
+            state: Option<Status>,
+        }
+
+        let schema = Schema::path("/issues/<status:enum>/<sort:new|old>?<state:open|closed?>").unwrap();
+        for (path, parameters) in [
+            ("/issues/open/new", Parameters{status: Status::Open, sort: "new".to_owned(), state: None}),
+            ("/issues/wont-fix/old?state=closed", Parameters{status: Status::WontFix, sort: "old".to_owned(), state: Some(Status::Closed)}),
+        ] {
+            assert_eq!(schema.parse::<_, Parameters>(path).unwrap(), parameters);
+            assert_eq!(schema.generate(&parameters).unwrap(), path);
+        }
+        match schema.parse::<_, Parameters>("/issues/Open/new") {
+            Err(StructPathError::InvalidChoice{got, expected}) => {
+                assert_eq!(got, "Open");
+                assert_eq!(expected, ["open", "closed", "wont-fix"]);
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+        match schema.parse::<_, Parameters>("/issues/open/newest") {
+            Err(StructPathError::InvalidChoice{got, expected}) => {
+                assert_eq!(got, "newest");
+                assert_eq!(expected, ["new", "old"]);
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(matches!(
+            schema.generate(&Parameters{status: Status::Open, sort: "newest".to_owned(), state: None}),
+            Err(StructPathError::InvalidChoice{..}),
+            ));
+        // choices can rule out some of the variants
+        assert!(schema.parse::<_, Parameters>("/issues/open/new?state=wont-fix").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_newtype() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct UserId(u64);
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Slug<'a>(&'a str);
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters<'a>{
+            user_id: UserId,
+            #[serde(borrow)]
+            slug: Slug<'a>,
+            page: Option<UserId>,
+            tag: Vec<Slug<'a>>,
+        }
+
+        let schema = Schema::path("/users/<user_id:u64>/<slug>?<page:u64?>&<tag..>").unwrap();
+        let path = "/users/12/hello?page=2&tag=a&tag=b";
+        let parameters = Parameters{user_id: UserId(12), slug: Slug("hello"), page: Some(UserId(2)), tag: vec![Slug("a"), Slug("b")]};
+        assert_eq!(schema.parse_str::<Parameters>(path).unwrap(), parameters);
+        assert_eq!(schema.generate(&parameters).unwrap(), path);
+        assert!(matches!(
+            schema.parse_str::<Parameters>("/users/x/hello"),
+            Err(StructPathError::ParseIntError(_)),
+            ));
+    }
+
+    #[test]
+    fn test_schema_path_bool_char() {
+        assert_eq!(
+            Schema::path("/<a:bool>/<b:bool(yes|no)>/<c:char>").unwrap(),
+            Schema::new()
+                .value("a", SegmentType::Bool(BoolFormat::TRUE_FALSE))
+                .value("b", SegmentType::Bool(BoolFormat::YES_NO))
+                .value("c", SegmentType::Char),
+            );
+        assert!(Schema::path("/<a:bool(yes)>").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_bool_char() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            archived: bool,
+            letter: char,
+            starred: Option<bool>,
+        }
+
+        let schema = Schema::new()
+            .literal("items")
+            .value("archived", SegmentType::Bool(BoolFormat::ONE_ZERO))
+            .value("letter", SegmentType::Char)
+            .optional_query_param("starred", SegmentType::Bool(BoolFormat::new("on", "off")));
+        for (path, parameters) in [
+            ("/items/1/a", Parameters{archived: true, letter: 'a', starred: None}),
+            ("/items/0/%C3%A9?starred=off", Parameters{archived: false, letter: 'é', starred: Some(false)}),
+        ] {
+            assert_eq!(schema.parse::<_, Parameters>(path).unwrap(), parameters);
+            assert_eq!(schema.generate(&parameters).unwrap(), path);
+        }
+        assert!(matches!(schema.parse::<_, Parameters>("/items/true/a"), Err(StructPathError::InvalidChoice{..})));
+        assert!(matches!(schema.parse::<_, Parameters>("/items/1/ab"), Err(StructPathError::ParseCharError(_))));
+        assert_eq!(
+            parse_path_generic("/items/1/a?starred=on".to_owned(), &schema).unwrap(),
+            {
+                let mut map = HashMap::new();
+                map.insert("archived".to_owned(), SegmentValue::Bool(true));
+                map.insert("letter".to_owned(), SegmentValue::Char('a'));
+                map.insert("starred".to_owned(), SegmentValue::Bool(true));
+                map
+            },
+            );
+    }
+
+    #[derive(Debug)]
+    struct ShortId;
+
+    impl SegmentParser for ShortId {
+        fn name(&self) -> &str {
+            "short_id"
+        }
+
+        fn validate(&self, segment: &str) -> Result<(), String> {
+            if segment.len() == 6 && segment.chars().all(|c| c.is_ascii_alphanumeric()) {
+                Ok(())
+            } else {
+                Err("expected 6 base62 characters".to_owned())
+            }
+        }
+    }
+
+    #[test]
+    fn test_schema_path_custom() {
+        assert!(matches!(Schema::path("/links/<id:short_id_unregistered>"), Err(PathSchemaParseError::UnrecognizedType{..})));
+        let registry = SegmentTypeRegistry::new().register(ShortId);
+        assert_eq!(
+            Schema::path_with_registry("/links/<id:short_id>", &registry).unwrap(),
+            Schema::new().literal("links").value("id", SegmentType::custom(ShortId)),
+            );
+        register_segment_type(FromStrParser::<std::net::Ipv4Addr>::new("ipv4_global_test"));
+        assert!(Schema::path("/hosts/<ip:ipv4_global_test>").is_ok());
+    }
+
+    #[test]
+    fn test_roundtrip_custom() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            id: String,
+            #[serde(with = "crate::display_fromstr")]
+            ip: std::net::Ipv4Addr,
+        }
+
+        let registry = SegmentTypeRegistry::new()
+            .register(ShortId)
+            .register(FromStrParser::<std::net::Ipv4Addr>::new("ip"));
+        let schema = Schema::path_with_registry("/links/<id:short_id>?<ip:ip>", &registry).unwrap();
+        let path = "/links/aZ09xy?ip=10.0.0.1";
+        let parameters = Parameters{id: "aZ09xy".to_owned(), ip: std::net::Ipv4Addr::new(10, 0, 0, 1)};
+        assert_eq!(schema.parse::<_, Parameters>(path).unwrap(), parameters);
+        assert_eq!(schema.generate(&parameters).unwrap(), path);
+        assert_eq!(
+            parse_path_generic(path.to_owned(), &schema).unwrap().get("id"),
+            Some(&SegmentValue::Custom{segment_type: "short_id".to_owned(), value: "aZ09xy".to_owned()}),
+            );
+        match schema.parse::<_, Parameters>("/links/abc?ip=10.0.0.1") {
+            Err(StructPathError::InvalidCustomValue{segment_type, got, ..}) => {
+                assert_eq!(segment_type, "short_id");
+                assert_eq!(got, "abc");
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(schema.parse::<_, Parameters>("/links/aZ09xy?ip=10.0.0").is_err());
+        assert!(matches!(
+            schema.generate(&Parameters{id: "abc".to_owned(), ip: std::net::Ipv4Addr::LOCALHOST}),
+            Err(StructPathError::InvalidCustomValue{..}),
+            ));
+    }
+
+    #[test]
+    fn test_schema_path_constraint() {
+        let schema = Schema::path("/posts/<slug:String(^[a-z0-9-]+$)>/<page:u32(1..=1000)>").unwrap();
+        assert_eq!(
+            schema,
+            Schema::new()
+                .literal("posts")
+                .constrained_value("slug", SegmentType::String, Constraint::regex("^[a-z0-9-]+$").unwrap())
+                .constrained_value("page", SegmentType::U32, Constraint::Range(
+                    std::ops::Bound::Included(SegmentValue::U32(1)),
+                    std::ops::Bound::Included(SegmentValue::U32(1000)),
+                    )),
+            );
+        assert_eq!(schema.segments()[2], SegmentSchema::Value(SegmentValueSchema{
+            name: "page".to_owned(),
+            segment_type: SegmentType::U32,
+            constraint: Some(Constraint::parse("1..=1000", &SegmentType::U32, &SegmentTypeRegistry::new()).unwrap()),
+        }));
+        assert!(Schema::path("/<a:String([)>").is_err());
+        assert!(Schema::path("/<a:u32(1..x)>").is_err());
+        assert!(Schema::path("/<a:u32(5)>").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_constraint() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters<'a>{
+            slug: &'a str,
+            page: u32,
+            tag: Option<String>,
+        }
+
+        let schema = Schema::path("/posts/<slug:String(^[a-z0-9-]+$)>/<page:u32(1..=1000)>?<tag:String(^#)?>").unwrap();
+        let path = "/posts/hello-world/2?tag=%23rust";
+        let parameters = Parameters{slug: "hello-world", page: 2, tag: Some("#rust".to_owned())};
+        assert_eq!(schema.parse_str::<Parameters>(path).unwrap(), parameters);
+        assert_eq!(schema.generate(&parameters).unwrap(), path);
+        match schema.parse_str::<Parameters>("/posts/hello-world/1001") {
+            Err(StructPathError::ConstraintViolation{field, constraint, got}) => {
+                assert_eq!(field, "page");
+                assert_eq!(constraint, "1..=1000");
+                assert_eq!(got, "1001");
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(matches!(schema.parse_str::<Parameters>("/posts/Hello/1"), Err(StructPathError::ConstraintViolation{..})));
+        assert!(matches!(schema.parse_str::<Parameters>("/posts/hello/1?tag=rust"), Err(StructPathError::ConstraintViolation{..})));
+        assert!(matches!(
+            parse_path_generic("/posts/hello/0".to_owned(), &schema),
+            Err(StructPathError::ConstraintViolation{..}),
+            ));
+        assert!(matches!(
+            schema.generate(&Parameters{slug: "Hello", page: 1, tag: None}),
+            Err(StructPathError::ConstraintViolation{..}),
+            ));
+    }
+
+    #[test]
+    fn test_value_with() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters{
+            code: String,
+        }
+
+        let schema = Schema::new()
+            .literal("codes")
+            .value_with("code", SegmentType::String, "three_letters", |code| code.len() == 3);
+        assert_eq!(schema.parse::<_, Parameters>("/codes/abc").unwrap(), Parameters{code: "abc".to_owned()});
+        match schema.parse::<_, Parameters>("/codes/abcd") {
+            Err(error @ StructPathError::ConstraintViolation{..}) => {
+                assert_eq!(error.to_string(), "Value \"abcd\" for \"code\" does not satisfy the constraint @three_letters");
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(schema.generate(&Parameters{code: "ab".to_owned()}).is_err());
+    }
+
+    #[test]
+    fn test_schema_path_pattern() {
+        assert_eq!(
+            Schema::path("/v<version:u32>/img/<w:u32>x<h:u32(..4096)>.png").unwrap(),
+            Schema::new()
+                .pattern(vec![PatternPart::literal("v"), PatternPart::value("version", SegmentType::U32)])
+                .literal("img")
+                .pattern(vec![
+                    PatternPart::value("w", SegmentType::U32),
+                    PatternPart::literal("x"),
+                    PatternPart::Value(SegmentValueSchema{
+                        name: "h".to_owned(),
+                        segment_type: SegmentType::U32,
+                        constraint: Some(Constraint::parse("..4096", &SegmentType::U32, &SegmentTypeRegistry::new()).unwrap()),
+                    }),
+                    PatternPart::literal(".png"),
+                ]),
+            );
+        assert!(Schema::path("/<a><b>").is_err());
+        assert!(Schema::path("/<a>.<b?>").is_err());
+        assert!(Schema::path("/<a>.<b..>").is_err());
+        assert!(Schema::path("/<a>.<b").is_err());
+    }
+
+    #[test]
+    fn test_unused_and_default_fields() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Params {
+            id: u64,
+            #[serde(default)]
+            page: u32,
+            #[serde(default = "default_sort")]
+            sort: String,
+            tag: Option<String>,
+        }
+
+        fn default_sort() -> String {
+            "name".to_owned()
+        }
+
+        let schema = Schema::path("/orgs/<org>/users/<id:u64>/<rest..>?<tag?>&<debug:bool?>").unwrap();
+        let params: Params = schema.parse("/orgs/acme/users/42/a/b?debug=true").unwrap();
+        assert_eq!(params, Params{id: 42, page: 0, sort: "name".to_owned(), tag: None});
+        // values nothing uses must still match the schema
+        assert!(matches!(schema.parse::<_, Params>("/orgs/acme/users/42?debug=maybe"), Err(StructPathError::InvalidChoice{..})));
+
+        let schema = schema.deny_unused_values(true);
+        assert_eq!(
+            schema.parse::<_, Params>("/orgs/acme/users/42").unwrap_err().to_string(),
+            "Value \"org\" is captured by the schema but not used",
+        );
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(deny_unknown_fields)]
+        struct Strict {
+            id: u64,
+        }
+        assert!(Schema::path("/<org>/<id:u64>").unwrap().parse::<_, Strict>("/acme/42").is_err());
+        assert_eq!(Schema::path("/<id:u64>").unwrap().parse::<_, Strict>("/42").unwrap(), Strict{id: 42});
+    }
+
+    #[test]
+    fn test_generate_map() {
+        let schema = Schema::path("/users/<id:u64>/<name>?<tag..>").unwrap();
+        let mut parameters = HashMap::new();
+        parameters.insert("id", "42");
+        parameters.insert("name", "ann b");
+        assert_eq!(schema.generate(&parameters).unwrap(), "/users/42/ann%20b");
+        let parameters = serde_json::json!({"id": 42, "name": "ann", "tag": ["x", "y"]});
+        assert_eq!(schema.generate(&parameters).unwrap(), "/users/42/ann?tag=x&tag=y");
+        assert!(matches!(schema.generate(&serde_json::json!({"id": 42})), Err(StructPathError::MissingField(_))));
+        assert!(matches!(
+            schema.generate(&serde_json::json!({"id": 42, "name": [{"a": 1}]})),
+            Err(StructPathError::NotSupported(_)),
+        ));
+    }
+
+    #[test]
+    fn test_generate_unused_values() {
+        let schema = Schema::path("/items/<id:u32>?<sort?>").unwrap();
+        let mut parameters = BTreeMap::new();
+        parameters.insert("id", "1");
+        parameters.insert("debug", "true");
+        parameters.insert("a b", "c&d");
+        assert_eq!(schema.generate(&parameters).unwrap(), "/items/1");
+        assert_eq!(
+            schema.clone().unused_keys(UnusedKeys::Deny).generate(&parameters).unwrap_err().to_string(),
+            "Parameter \"a b\" has no value in the schema",
+        );
+        parameters.insert("sort", "name");
+        assert_eq!(schema.clone().deny_unused_values(true).generate(&parameters).unwrap(), "/items/1?sort=name");
+        assert_eq!(
+            schema.clone().unused_keys(UnusedKeys::Query).generate(&parameters).unwrap(),
+            "/items/1?sort=name&a%20b=c%26d&debug=true",
+        );
+
+        #[derive(Serialize)]
+        struct Item {
+            id: u32,
+            tags: Vec<&'static str>,
+            note: Option<&'static str>,
+        }
+        let item = Item{id: 1, tags: vec!["x", "y"], note: None};
+        assert_eq!(schema.clone().unused_keys(UnusedKeys::Query).generate(&item).unwrap(), "/items/1?tags=x&tags=y");
+        assert!(matches!(schema.unused_keys(UnusedKeys::Deny).generate(&item), Err(StructPathError::UnknownParameter(_))));
+    }
+
+    #[test]
+    fn test_roundtrip_nested() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct OrgRef{
+            id: u64,
+            name: String,
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Owner{
+            login: String,
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct OrgRepo{
+            org: OrgRef,
+            repo: String,
+            owner: Option<Owner>,
+        }
+
+        let schema = Schema::path("/orgs/<org.id:u64>/<repo>/<org.name>?<owner.login?>").unwrap();
+        let mut org_repo = OrgRepo{org: OrgRef{id: 7, name: "acme".to_owned()}, repo: "widgets".to_owned(), owner: None};
+        assert_eq!(schema.generate(&org_repo).unwrap(), "/orgs/7/widgets/acme");
+        assert_eq!(schema.parse::<_, OrgRepo>("/orgs/7/widgets/acme").unwrap(), org_repo);
+        org_repo.owner = Some(Owner{login: "ann".to_owned()});
+        assert_eq!(schema.generate(&org_repo).unwrap(), "/orgs/7/widgets/acme?owner.login=ann");
+        assert_eq!(schema.parse::<_, OrgRepo>("/orgs/7/widgets/acme?owner.login=ann").unwrap(), org_repo);
+        assert!(matches!(schema.parse::<_, OrgRepo>("/orgs/x/widgets/acme"), Err(StructPathError::ParseIntError(_))));
+
+        let values: BTreeMap<String, BTreeMap<String, String>> = Schema::path("/<a.x>/<b.y>/<a.z>").unwrap().parse("/1/2/3").unwrap();
+        assert_eq!(values["a"]["x"], "1");
+        assert_eq!(values["a"]["z"], "3");
+        assert_eq!(values["b"]["y"], "2");
+
+        // nested values a struct doesn't use are still checked, or can be denied
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Repo{
+            repo: String,
+        }
+        assert_eq!(schema.parse::<_, Repo>("/orgs/7/widgets/acme").unwrap(), Repo{repo: "widgets".to_owned()});
+        assert!(schema.parse::<_, Repo>("/orgs/x/widgets/acme").is_err());
+        assert!(matches!(
+            schema.deny_unused_values(true).parse::<_, Repo>("/orgs/7/widgets/acme"),
+            Err(StructPathError::UnusedValue(name)) if name == "org.id",
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip_flatten() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Common{
+            org: String,
+            version: u8,
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Issue{
+            #[serde(flatten)]
+            common: Common,
+            number: u32,
+            labels: Vec<String>,
+        }
+
+        let schema = Schema::path("/<org>/v<version:u8>/issues/<number:u32>?<labels..>").unwrap();
+        let issue = Issue{common: Common{org: "acme".to_owned(), version: 2}, number: 12, labels: vec!["bug".to_owned()]};
+        assert_eq!(schema.generate(&issue).unwrap(), "/acme/v2/issues/12?labels=bug");
+        assert_eq!(schema.parse::<_, Issue>("/acme/v2/issues/12?labels=bug").unwrap(), issue);
+        assert!(schema.parse::<_, Issue>("/acme/v300/issues/12").is_err());
+
+        // flattened fields get the value's type, so they can't take it as a string
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Build{
+            build: String,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Release{
+            #[serde(flatten)]
+            build: Build,
+        }
+
+        let error = Schema::path("/builds/<build:u64>").unwrap().parse::<_, Release>("/builds/42").unwrap_err();
+        assert!(error.to_string().contains("invalid type: integer `42`"));
+        let release: Release = Schema::path("/builds/<build>").unwrap().parse("/builds/42").unwrap();
+        assert_eq!(release.build.build, "42");
+    }
+
+    #[test]
+    fn test_parse_untyped() {
+        let schema = Schema::path("/users/<id:u64>/<name>/<rest..>?<on:bool(yes|no)?>").unwrap();
+        let values: HashMap<String, String> = schema.parse("/users/42/ann%20b/a/b?on=yes").unwrap();
+        assert_eq!(values["id"], "42");
+        assert_eq!(values["name"], "ann b");
+        assert_eq!(values["rest"], "a/b");
+        assert_eq!(values["on"], "yes");
+        let values: HashMap<String, &str> = schema.parse_str("/users/42/ann/a").unwrap();
+        assert_eq!(values.len(), 3);
+        assert!(matches!(schema.parse::<_, HashMap<String, String>>("/users/x/ann/a"), Err(StructPathError::ParseIntError(_))));
+
+        let schema = Schema::path("/users/<id:u64>/<name>/<rest..>?<tag:u8..>&<on:bool?>").unwrap();
+        let values: serde_json::Value = schema.parse("/users/42/ann/a/b?tag=1&tag=2&on=true").unwrap();
+        assert_eq!(values, serde_json::json!({"id": 42, "name": "ann", "rest": ["a", "b"], "tag": [1, 2], "on": true}));
+    }
+
+    #[test]
+    fn test_parse_generic() {
+        let schema = Schema::path("/users/<id:u64>/<name>.<ext:png|jpg>/<page:u32?>?<tag:char..>").unwrap();
+        let values = schema.parse_generic("/users/42/me.png?tag=a&tag=b").unwrap();
+        assert_eq!(values.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["id", "name", "ext", "tag"]);
+        assert_eq!(values[0].1, SegmentValue::U64(42));
+        assert_eq!(values[2].1.to_string(), "png");
+        assert_eq!(values[3].1.to_string(), "a/b");
+        assert_eq!(
+            serde_json::to_value(&values).unwrap(),
+            serde_json::json!([["id", 42], ["name", "me"], ["ext", "png"], ["tag", ["a", "b"]]]),
+        );
+        assert!(schema.parse_generic("/users/me/me.png").is_err());
+
+        // values keep the schema's order rather than being sorted by name
+        let values = Schema::path("/<z>/<a>").unwrap().parse_generic("/1/2").unwrap();
+        assert_eq!(values, vec![("z".to_owned(), SegmentValue::String("1".to_owned())), ("a".to_owned(), SegmentValue::String("2".to_owned()))]);
+    }
+
+    #[test]
+    fn test_introspection() {
+        let schema = Schema::path("/users/all/<id:u64>/<name>.<ext:png|jpg>/<rest..>?<page:u32?>").unwrap();
+        assert_eq!(schema.segment_count(), 5);
+        assert_eq!(schema.segments()[0], SegmentSchema::Literal("users".to_owned()));
+        assert_eq!(schema.literal_prefix(), "/users/all");
+        assert!(!schema.is_static());
+        let choice = SegmentType::Choice(vec!["png".to_owned(), "jpg".to_owned()]);
+        assert_eq!(
+            schema.fields(),
+            vec![
+                ("id", &SegmentType::U64),
+                ("name", &SegmentType::String),
+                ("ext", &choice),
+                ("rest", &SegmentType::String),
+                ("page", &SegmentType::U32),
+            ],
+        );
+
+        let schema = Schema::path("/a b/c").unwrap();
+        assert!(schema.is_static());
+        assert_eq!(schema.literal_prefix(), "/a%20b/c");
+        assert!(schema.fields().is_empty());
+        assert!(!schema.clone().match_mode(MatchMode::Prefix).is_static());
+        assert!(!Schema::path("/a?<b?>").unwrap().is_static());
+        assert_eq!(Schema::path("/<a>/b").unwrap().literal_prefix(), "");
+        assert!(Schema::new().is_static());
+    }
+
+    #[test]
+    fn test_roundtrip_pattern() {
+        #[derive(Deserialize, Serialize, PartialEq, Debug)]
+        struct Parameters<'a>{
+            w: u32,
+            h: u32,
+            name: &'a str,
+            ext: Cow<'a, str>,
+        }
+
+        let schema = Schema::path("/img/<w:u32>x<h:u32>/<name>.<ext>").unwrap();
+        for (path, parameters) in [
+            ("/img/640x480/cat.png", Parameters{w: 640, h: 480, name: "cat", ext: Cow::Borrowed("png")}),
+            ("/img/1x1/cat.min.png", Parameters{w: 1, h: 1, name: "cat.min", ext: Cow::Borrowed("png")}),
+            ("/img/1x1/ab.p%20g", Parameters{w: 1, h: 1, name: "ab", ext: Cow::Owned("p g".to_owned())}),
+        ] {
+            assert_eq!(schema.parse_str::<Parameters>(path).unwrap(), parameters);
+            assert_eq!(schema.generate(&parameters).unwrap(), path);
+        }
+        assert_eq!(
+            parse_path_generic("/img/2x3/cat.png".to_owned(), &schema).unwrap(),
+            {
+                let mut map = HashMap::new();
+                map.insert("w".to_owned(), SegmentValue::U32(2));
+                map.insert("h".to_owned(), SegmentValue::U32(3));
+                map.insert("name".to_owned(), SegmentValue::String("cat".to_owned()));
+                map.insert("ext".to_owned(), SegmentValue::String("png".to_owned()));
+                map
+            },
+            );
+        match schema.parse_str::<Parameters>("/img/2by3/cat.png") {
+            Err(StructPathError::IncorrectSegment{got, expected}) => {
+                assert_eq!(got, "2by3");
+                assert_eq!(expected, "<w>x<h>");
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(schema.parse_str::<Parameters>("/img/2x3/cat").is_err());
+        assert!(matches!(
+            schema.generate(&Parameters{w: 1, h: 1, name: "cat", ext: Cow::Borrowed("min.png")}),
+            Err(StructPathError::AmbiguousPattern(segment)) if segment == "cat.min.png",
+            ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Tail segment must be the last segment")]
+    fn test_builder_literal_after_tail() {
+        // a literal after a tail would never be checked, e.g. `/x/y` would parse as `{"rest": ["x", "y"]}`
+        Schema::new().tail("rest").literal("b");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only optional segments may follow an optional segment")]
+    fn test_builder_literal_after_optional() {
+        // `/b` would be parsed as the optional value, failing with a `ParseIntError`
+        Schema::new().optional_value("p", SegmentType::U32).literal("b");
+    }
+
+    #[test]
+    fn test_try_segment() {
+        let schema = Schema::new().try_segment(SegmentSchema::Literal("static".to_owned())).unwrap()
+            .try_segment(SegmentSchema::Tail("rest".to_owned())).unwrap();
+        assert_eq!(schema, Schema::path("/static/<rest..>").unwrap());
+        assert_eq!(
+            schema.try_segment(SegmentSchema::Literal("b".to_owned())).unwrap_err().to_string(),
+            "Invalid segment: Tail segment must be the last segment, can't append Literal(\"b\")",
+        );
+        let schema = Schema::new().optional_value("p", SegmentType::U32);
+        let value = SegmentValueSchema{name: "q".to_owned(), segment_type: SegmentType::U8, constraint: None};
+        assert!(matches!(schema.clone().try_segment(SegmentSchema::Value(value.clone())), Err(StructPathError::InvalidSegment(_))));
+        assert!(schema.try_segment(SegmentSchema::Optional(value)).is_ok());
+
+        let value = || PatternPart::value("a", SegmentType::String);
+        let literal = || PatternPart::literal(".");
+        for parts in [vec![value()], vec![literal()], vec![value(), value(), literal()], vec![value(), literal(), literal()], vec![value(), PatternPart::literal("")]] {
+            let error = Schema::new().try_segment(SegmentSchema::Pattern(parts.clone())).unwrap_err();
+            assert!(matches!(error, StructPathError::InvalidSegment(_)), "{:?}", parts);
+        }
+        assert!(Schema::new().try_segment(SegmentSchema::Pattern(vec![literal(), value(), literal()])).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only optional segments may follow an optional segment")]
+    fn test_builder_value_after_optional() {
+        Schema::new().optional_value("p", SegmentType::U32).value("q", SegmentType::U32);
+    }
+
+    #[test]
+    fn test_builder_optional_values() {
+        let schema = Schema::new().literal("a").optional_value("p", SegmentType::U32).optional_value("q", SegmentType::String);
+        assert_eq!(schema, Schema::path("/a/<p:u32?>/<q?>").unwrap());
+    }
 }
@@ -0,0 +1,185 @@
+//! Segments mixing literal text with several values, e.g. `<name>.<ext>`.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+
+use crate::{SegmentType, SegmentValueSchema, StructPathError};
+
+/// PatternPart is a piece of a `SegmentSchema::Pattern`
+///
+/// `Literal` is text which must appear as is
+///
+/// `Value` is a value to be parsed, just like a `SegmentSchema::Value` but only taking up part
+/// of the segment
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternPart {
+    Literal(String),
+    Value(SegmentValueSchema),
+}
+
+impl PatternPart {
+    /// A `PatternPart::Literal`
+    pub fn literal<S: Into<String>>(literal: S) -> Self {
+        PatternPart::Literal(literal.into())
+    }
+
+    /// A `PatternPart::Value`
+    pub fn value<S: Into<String>>(name: S, segment_type: SegmentType) -> Self {
+        PatternPart::Value(SegmentValueSchema{name: name.into(), segment_type, constraint: None})
+    }
+}
+
+/// The pattern as it's written in a path schema, with only the names of its values
+pub(crate) fn pattern_text(parts: &[PatternPart]) -> String {
+    parts.iter().map(|part| match part {
+        PatternPart::Literal(literal) => literal.clone(),
+        PatternPart::Value(segment_value_schema) => format!("<{}>", segment_value_schema.name),
+    }).collect()
+}
+
+/// Split a raw (percent-encoded) segment into the byte range of each value in a pattern
+///
+/// Values are matched greedily, each taking as much of the segment as it can while still letting
+/// the rest of the pattern match, e.g. `<name>.<ext>` splits `archive.tar.gz` into `archive.tar`
+/// and `gz`. Each value must parse according to its `SegmentValueSchema`.
+pub(crate) fn split_pattern<'p>(parts: &'p [PatternPart], raw_segment: &str) -> Result<Vec<(&'p SegmentValueSchema, Range<usize>)>, StructPathError> {
+    let mut matcher = Matcher{raw_segment, captures: vec![], failed: HashSet::new()};
+    if matcher.match_parts(parts, 0) {
+        Ok(matcher.captures)
+    } else {
+        Err(StructPathError::IncorrectSegment{
+            got: percent_decode_str(raw_segment).decode_utf8_lossy().into_owned(),
+            expected: pattern_text(parts),
+        })
+    }
+}
+
+struct Matcher<'p, 'r> {
+    raw_segment: &'r str,
+    captures: Vec<(&'p SegmentValueSchema, Range<usize>)>,
+    /// The (remaining parts, start) pairs already known not to match, so that each is only tried
+    /// once however many ways the values before it can be split
+    failed: HashSet<(usize, usize)>,
+}
+
+impl<'p, 'r> Matcher<'p, 'r> {
+    fn match_parts(&mut self, parts: &'p [PatternPart], start: usize) -> bool {
+        if self.failed.contains(&(parts.len(), start)) {
+            return false;
+        }
+        let matched = match parts.split_first() {
+            None => start == self.raw_segment.len(),
+            Some((PatternPart::Literal(literal), parts)) => match encoded_prefix_len(&self.raw_segment[start..], literal) {
+                Some(len) => self.match_parts(parts, start + len),
+                None => false,
+            },
+            Some((PatternPart::Value(segment_value_schema), parts)) => self.match_value(segment_value_schema, parts, start),
+        };
+        if !matched {
+            self.failed.insert((parts.len(), start));
+        }
+        matched
+    }
+
+    fn match_value(&mut self, segment_value_schema: &'p SegmentValueSchema, parts: &'p [PatternPart], start: usize) -> bool {
+        let raw_segment = self.raw_segment;
+        let mark = self.captures.len();
+        for end in (start..=raw_segment.len()).rev().filter(|end| raw_segment.is_char_boundary(*end)) {
+            self.captures.push((segment_value_schema, start..end));
+            // the rest of the pattern is cheaper to rule out than parsing this value
+            if self.match_parts(parts, end) && percent_decode_str(&raw_segment[start..end]).decode_utf8()
+                .is_ok_and(|segment| segment_value_schema.parse(&segment).is_ok()) {
+                return true;
+            }
+            self.captures.truncate(mark);
+        }
+        false
+    }
+}
+
+/// The length of `literal` at the start of a raw segment, whether or not any of it is percent-encoded
+fn encoded_prefix_len(raw: &str, literal: &str) -> Option<usize> {
+    let raw = raw.as_bytes();
+    let mut index = 0;
+    for byte in literal.bytes() {
+        if byte != b'%' && raw.get(index) == Some(&byte) {
+            index += 1;
+        } else if raw.get(index) == Some(&b'%') && raw.get(index + 1..index + 3).and_then(decode_hex) == Some(byte) {
+            index += 3;
+        } else {
+            return None;
+        }
+    }
+    Some(index)
+}
+
+fn decode_hex(hex: &[u8]) -> Option<u8> {
+    let digit = |digit: u8| (digit as char).to_digit(16);
+    Some((digit(hex[0])? * 16 + digit(hex[1])?) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split<'s>(parts: &[PatternPart], raw_segment: &'s str) -> Option<Vec<&'s str>> {
+        split_pattern(parts, raw_segment).ok().map(|captures| {
+            captures.into_iter().map(|(_, range)| &raw_segment[range]).collect()
+        })
+    }
+
+    #[test]
+    fn test_split_pattern() {
+        let file = [PatternPart::value("name", SegmentType::String), PatternPart::literal("."), PatternPart::value("ext", SegmentType::String)];
+        assert_eq!(split(&file, "notes.txt"), Some(vec!["notes", "txt"]));
+        assert_eq!(split(&file, "archive.tar.gz"), Some(vec!["archive.tar", "gz"]));
+        assert_eq!(split(&file, "archive%2Etar"), Some(vec!["archive", "tar"]));
+        assert_eq!(split(&file, "notes"), None);
+
+        let size = [
+            PatternPart::value("w", SegmentType::U32),
+            PatternPart::literal("x"),
+            PatternPart::value("h", SegmentType::U32),
+            PatternPart::literal(".png"),
+        ];
+        assert_eq!(split(&size, "640x480.png"), Some(vec!["640", "480"]));
+        assert_eq!(split(&size, "640x480.jpg"), None);
+        assert_eq!(split(&size, "axb.png"), None);
+
+        let version = [PatternPart::literal("v"), PatternPart::value("version", SegmentType::U32)];
+        assert_eq!(split(&version, "v2"), Some(vec!["2"]));
+        assert_eq!(split(&version, "2"), None);
+        assert_eq!(pattern_text(&size), "<w>x<h>.png");
+    }
+
+    #[test]
+    fn test_adjacent_values() {
+        let parts = [PatternPart::value("a", SegmentType::String), PatternPart::value("b", SegmentType::U8)];
+        // the first value is greedy, leaving as little as possible for the second
+        assert_eq!(split(&parts, "abc12"), Some(vec!["abc1", "2"]));
+        assert_eq!(split(&parts, "é"), None);
+    }
+
+    #[test]
+    fn test_adversarial_segment() {
+        // without remembering failed splits this backtracks over every way of splitting the `x`s
+        let parts = [
+            PatternPart::value("a", SegmentType::String),
+            PatternPart::literal("x"),
+            PatternPart::value("b", SegmentType::String),
+            PatternPart::literal("x"),
+            PatternPart::value("c", SegmentType::String),
+            PatternPart::literal("x"),
+            PatternPart::value("d", SegmentType::U8),
+        ];
+        let raw_segment = "x".repeat(1000);
+        let start = std::time::Instant::now();
+        assert_eq!(split(&parts, &raw_segment), None);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(split(&parts, &format!("{raw_segment}7")).map(|values| values[3]), Some("7"));
+    }
+}
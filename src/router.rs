@@ -0,0 +1,571 @@
+//! Matching a path against many `Schema`s at once.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use percent_encoding::percent_decode_str;
+
+use crate::{
+    deserialize_values, find_conflicts, generate_variant_path, parse_path_generic, parse_variant_path, split_query, Conflict, MatchMode,
+    PatternPart, Schema, SegmentSchema, SegmentType, SegmentValue, SegmentValueSchema, StructPathError,
+};
+use crate::pattern::split_pattern;
+
+/// Priority of a `SegmentType` when several value segments could match the same path segment,
+/// lower values are tried first.
+///
+/// Choices and booleans are tried first, then integers before floats (narrowest first, unsigned
+/// before signed), then single characters and custom types. `String` and `Enum` are always tried
+/// last, since they match anything.
+pub(crate) fn type_priority(segment_type: &SegmentType) -> usize {
+    match segment_type {
+        SegmentType::Choice(_) | SegmentType::Bool(_) => 0,
+        SegmentType::U8 => 1,
+        SegmentType::U16 => 2,
+        SegmentType::U32 => 3,
+        SegmentType::U64 => 4,
+        SegmentType::U128 => 5,
+        SegmentType::I8 => 6,
+        SegmentType::I16 => 7,
+        SegmentType::I32 => 8,
+        SegmentType::I64 => 9,
+        SegmentType::I128 => 10,
+        SegmentType::F32 => 11,
+        SegmentType::F64 => 12,
+        SegmentType::Char => 13,
+        SegmentType::Custom(_) => 14,
+        SegmentType::String | SegmentType::Enum => 15,
+    }
+}
+
+/// Priority of a value segment, lower values are tried first
+///
+/// Follows `type_priority`, with constrained values tried before unconstrained values of the
+/// same type.
+pub(crate) fn value_priority(segment_value_schema: &SegmentValueSchema) -> usize {
+    2 * type_priority(&segment_value_schema.segment_type) + segment_value_schema.constraint.is_none() as usize
+}
+
+/// A node in the segment trie used by `Router`
+///
+/// Each field holds indices into `Router::routes`, kept in registration order so the first
+/// registered route wins when two routes are otherwise identical.
+#[derive(Debug)]
+struct Node {
+    literals: HashMap<String, Node>,
+    patterns: Vec<(Vec<PatternPart>, Node)>,
+    values: Vec<(SegmentValueSchema, Node)>,
+    endpoints: Vec<usize>,
+    tails: Vec<usize>,
+    prefixes: Vec<usize>,
+}
+
+impl Node {
+    fn new() -> Self {
+        Self{
+            literals: HashMap::new(),
+            patterns: vec![],
+            values: vec![],
+            endpoints: vec![],
+            tails: vec![],
+            prefixes: vec![],
+        }
+    }
+
+    fn value_child(&mut self, segment_value_schema: &SegmentValueSchema) -> &mut Node {
+        // values with the same type and constraint share a node, whatever they're called
+        let position = match self.values.iter().position(|(existing, _)| {
+            existing.segment_type == segment_value_schema.segment_type && existing.constraint == segment_value_schema.constraint
+        }) {
+            Some(position) => position,
+            None => {
+                let priority = value_priority(segment_value_schema);
+                let position = self.values.iter()
+                    .position(|(existing, _)| value_priority(existing) > priority)
+                    .unwrap_or(self.values.len());
+                self.values.insert(position, (segment_value_schema.clone(), Node::new()));
+                position
+            },
+        };
+        &mut self.values[position].1
+    }
+
+    fn pattern_child(&mut self, parts: &[PatternPart]) -> &mut Node {
+        let position = match self.patterns.iter().position(|(existing, _)| existing == parts) {
+            Some(position) => position,
+            None => {
+                self.patterns.push((parts.to_vec(), Node::new()));
+                self.patterns.len() - 1
+            },
+        };
+        &mut self.patterns[position].1
+    }
+
+    fn insert(&mut self, schema: &Schema, route: usize) {
+        let mut node = self;
+        for segment_schema in &schema.segments {
+            node = match segment_schema {
+                SegmentSchema::Literal(literal) => node.literals.entry(literal.clone()).or_insert_with(Node::new),
+                SegmentSchema::Value(segment_value_schema) => node.value_child(segment_value_schema),
+                SegmentSchema::Optional(segment_value_schema) => {
+                    // the path may stop before any optional segment
+                    node.end(schema, route);
+                    node.value_child(segment_value_schema)
+                },
+                SegmentSchema::Pattern(parts) => node.pattern_child(parts),
+                SegmentSchema::Tail(_) => {
+                    node.tails.push(route);
+                    return;
+                },
+            };
+        }
+        node.end(schema, route);
+    }
+
+    fn end(&mut self, schema: &Schema, route: usize) {
+        match schema.match_mode {
+            MatchMode::Strict => self.endpoints.push(route),
+            MatchMode::Prefix => self.prefixes.push(route),
+        }
+    }
+
+    /// Depth first search through the routes matching `segments` in priority order, each segment
+    /// given both raw and percent-decoded, for the first route `accept` takes
+    fn find(&self, segments: &[(&str, Cow<str>)], accept: &mut dyn FnMut(usize) -> bool) -> Option<usize> {
+        match segments.split_first() {
+            None => {
+                if let Some(route) = self.endpoints.iter().copied().find(|route| accept(*route)) {
+                    return Some(route);
+                }
+            },
+            Some(((raw_segment, segment), rest)) => {
+                if let Some(route) = self.literals.get(segment.as_ref()).and_then(|child| child.find(rest, accept)) {
+                    return Some(route);
+                }
+                for (parts, child) in &self.patterns {
+                    if split_pattern(parts, raw_segment).is_err() {
+                        continue;
+                    }
+                    if let Some(route) = child.find(rest, accept) {
+                        return Some(route);
+                    }
+                }
+                for (segment_value_schema, child) in &self.values {
+                    if segment_value_schema.parse(segment).is_err() {
+                        continue;
+                    }
+                    if let Some(route) = child.find(rest, accept) {
+                        return Some(route);
+                    }
+                }
+            },
+        }
+        self.tails.iter().chain(&self.prefixes).copied().find(|route| accept(*route))
+    }
+}
+
+/// Router matches paths against many `Schema`s, each paired with a handler
+///
+/// When more than one `Schema` matches a path, the winner is chosen segment by segment from the
+/// start of the path: literals beat patterns mixing literal text and values, which beat value
+/// segments, value segments with numeric types beat `String` value segments (see below) and tails
+/// are only tried last. If two schemas are still tied, the one registered first wins. A winner
+/// whose values don't all parse, e.g. because a required query parameter is missing, is passed
+/// over for the next schema in line.
+///
+/// Numeric value segments are tried narrowest type first, unsigned before signed and integers
+/// before floats. Value segments with a `Constraint` are tried before those of the same type
+/// without one, e.g. `/<slug:String(^[a-z-]+$)>` before `/<name>`.
+///
+/// # Examples
+///
+/// ```
+/// use structpath::{Router, Schema};
+///
+/// let router = Router::new()
+///     .route(Schema::path("/users/me").unwrap(), "me")
+///     .route(Schema::path("/users/<id:u64>").unwrap(), "by_id")
+///     .route(Schema::path("/users/<name>").unwrap(), "by_name");
+///
+/// assert_eq!(*router.at("/users/me").unwrap().handler, "me");
+/// assert_eq!(*router.at("/users/12").unwrap().handler, "by_id");
+/// assert_eq!(*router.at("/users/ben").unwrap().handler, "by_name");
+/// assert!(router.at("/posts/12").is_none());
+/// ```
+#[derive(Debug)]
+pub struct Router<H> {
+    root: Node,
+    routes: Vec<(Schema, H)>,
+}
+
+/// RouteMatch is the result of successfully matching a path with a `Router`
+#[derive(Debug)]
+pub struct RouteMatch<'r, H> {
+    /// The handler registered with the matching `Schema`
+    pub handler: &'r H,
+    /// The matching `Schema`
+    pub schema: &'r Schema,
+    /// The values parsed out of the path by `schema`
+    pub values: HashMap<String, SegmentValue>,
+}
+
+impl<'r, H> RouteMatch<'r, H> {
+    /// Deserialize the values parsed from the path into a value, without parsing the path again
+    pub fn parse<'m, T>(&'m self) -> Result<T, StructPathError> where T: serde::Deserialize<'m> {
+        deserialize_values(&self.values, self.schema)
+    }
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H> Router<H> {
+    /// Create an empty `Router`
+    pub fn new() -> Self {
+        Self{root: Node::new(), routes: vec![]}
+    }
+
+    /// Register a `Schema` and its handler, builder style
+    pub fn route(mut self, schema: Schema, handler: H) -> Self {
+        self.add(schema, handler);
+        self
+    }
+
+    /// Register a `Schema` and its handler
+    pub fn add(&mut self, schema: Schema, handler: H) {
+        let route = self.routes.len();
+        self.root.insert(&schema, route);
+        self.routes.push((schema, handler));
+    }
+
+    /// Find the handler for a path, along with the values parsed from it
+    ///
+    /// Returns `None` if no registered `Schema` matches the path.
+    pub fn at(&self, path: &str) -> Option<RouteMatch<'_, H>> {
+        let mut values = None;
+        let (schema, handler) = self.find(path, |schema, _| {
+            values = parse_path_generic(path.to_owned(), schema).ok();
+            values.is_some()
+        })?;
+        Some(RouteMatch{handler, schema, values: values?})
+    }
+
+    /// The highest priority `Schema` matching a path which `accept` takes, along with its handler
+    fn find(&self, path: &str, mut accept: impl FnMut(&Schema, &H) -> bool) -> Option<&(Schema, H)> {
+        let mut segments = vec![];
+        for raw_segment in split_query(path).0.split("/").skip(1) {
+            segments.push((raw_segment, percent_decode_str(raw_segment).decode_utf8().ok()?));
+        }
+        let route = self.root.find(&segments, &mut |route| {
+            let (schema, handler) = &self.routes[route];
+            accept(schema, handler)
+        })?;
+        Some(&self.routes[route])
+    }
+
+    /// Report every pair of registered schemas which can match the same path, see `find_conflicts`
+    ///
+    /// Indices in the returned `Conflict`s are in registration order.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        find_conflicts(self.routes.iter().map(|(schema, _)| schema))
+    }
+
+    /// Find the handler for a path and deserialize the values parsed from it into a value
+    ///
+    /// The values are dropped once they're deserialized, so `T` can't borrow from them, use `at`
+    /// and `RouteMatch::parse` for that.
+    pub fn parse<T>(&self, path: &str) -> Option<(&H, Result<T, StructPathError>)> where T: serde::de::DeserializeOwned {
+        let route_match = self.at(path)?;
+        Some((route_match.handler, route_match.parse()))
+    }
+}
+
+/// EnumRouter parses paths into an enum and generates paths from it, with a `Schema` for each variant
+///
+/// Variants are named as serde names them, and paths are matched with the same rules as `Router`.
+/// Struct variants are filled in from the path's values, a newtype variant's value is parsed from
+/// them as a whole, and unit variants take no values. `#[derive(StructPath)]` builds one from a
+/// `#[structpath("...")]` attribute on each variant.
+///
+/// # Examples
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use structpath::{EnumRouter, Schema};
+///
+/// #[derive(Deserialize, Serialize, PartialEq, Debug)]
+/// enum Route {
+///     Home,
+///     User{id: u64},
+///     Repo{org: String, name: String},
+/// }
+///
+/// let router = EnumRouter::new()
+///     .variant("Home", Schema::path("/").unwrap())
+///     .variant("User", Schema::path("/users/<id:u64>").unwrap())
+///     .variant("Repo", Schema::path("/repos/<org>/<name>").unwrap());
+///
+/// assert_eq!(router.parse::<Route>("/users/12").unwrap(), Route::User{id: 12});
+/// assert_eq!(router.generate(&Route::Repo{org: "acme".to_owned(), name: "widgets".to_owned()}).unwrap(), "/repos/acme/widgets");
+/// assert_eq!(router.generate(&Route::Home).unwrap(), "/");
+/// ```
+#[derive(Debug, Default)]
+pub struct EnumRouter {
+    router: Router<&'static str>,
+}
+
+impl EnumRouter {
+    /// Create an empty `EnumRouter`
+    pub fn new() -> Self {
+        Self{router: Router::new()}
+    }
+
+    /// Register the `Schema` for a variant, builder style
+    pub fn variant(mut self, variant: &'static str, schema: Schema) -> Self {
+        self.add(variant, schema);
+        self
+    }
+
+    /// Register the `Schema` for a variant
+    pub fn add(&mut self, variant: &'static str, schema: Schema) {
+        self.router.add(schema, variant);
+    }
+
+    /// The `Schema` registered for a variant
+    pub fn schema(&self, variant: &str) -> Option<&Schema> {
+        self.router.routes.iter().find(|(_, name)| *name == variant).map(|(schema, _)| schema)
+    }
+
+    /// The underlying `Router`, with each `Schema`'s variant as its handler, e.g. to check for conflicts
+    pub fn router(&self) -> &Router<&'static str> {
+        &self.router
+    }
+
+    /// Parse a path into the variant whose `Schema` matches it
+    ///
+    /// Like `Router::at`, a variant whose values don't parse is passed over for the next in line.
+    /// If none of them parse, the error from the highest priority one is returned.
+    pub fn parse<'p, T>(&self, path: &'p str) -> Result<T, StructPathError> where T: serde::Deserialize<'p> {
+        let mut parsed = None;
+        self.router.find(path, |schema, variant| {
+            let result = parse_variant_path(path, schema, variant);
+            let accepted = result.is_ok();
+            if accepted || parsed.is_none() {
+                parsed = Some(result);
+            }
+            accepted
+        });
+        parsed.unwrap_or_else(|| Err(StructPathError::NoMatchingRoute(path.to_owned())))
+    }
+
+    /// Generate the path for a variant, using the `Schema` registered for it
+    pub fn generate<T>(&self, value: &T) -> Result<String, StructPathError> where T: serde::Serialize {
+        generate_variant_path(value, |variant| self.schema(variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    fn router() -> Router<&'static str> {
+        Router::new()
+            .route(Schema::path("/users/<name>").unwrap(), "by_name")
+            .route(Schema::path("/users/<id:i64>").unwrap(), "by_signed_id")
+            .route(Schema::path("/users/<id:u64>").unwrap(), "by_id")
+            .route(Schema::path("/users/me").unwrap(), "me")
+            .route(Schema::path("/users/<id:u64>/posts/<page:u64?>").unwrap(), "posts")
+            .route(Schema::path("/static/<path..>").unwrap(), "static")
+            .route(Schema::path("/static/favicon.ico").unwrap(), "favicon")
+            .route(Schema::path("/api").unwrap().match_mode(MatchMode::Prefix), "api")
+    }
+
+    #[test]
+    fn test_priority() {
+        let router = router();
+        assert_eq!(*router.at("/users/me").unwrap().handler, "me");
+        assert_eq!(*router.at("/users/1").unwrap().handler, "by_id");
+        assert_eq!(*router.at("/users/-1").unwrap().handler, "by_signed_id");
+        assert_eq!(*router.at("/users/ben").unwrap().handler, "by_name");
+        assert_eq!(*router.at("/static/favicon.ico").unwrap().handler, "favicon");
+        assert_eq!(*router.at("/static/css/main.css").unwrap().handler, "static");
+        assert_eq!(*router.at("/api/v1/things").unwrap().handler, "api");
+    }
+
+    #[test]
+    fn test_optional_and_backtracking() {
+        let router = router();
+        assert_eq!(*router.at("/users/1/posts").unwrap().handler, "posts");
+        assert_eq!(*router.at("/users/1/posts/2").unwrap().handler, "posts");
+        assert!(router.at("/users/ben/posts").is_none());
+        assert!(router.at("/users/1/posts/2/3").is_none());
+        assert!(router.at("/nothing").is_none());
+    }
+
+    #[test]
+    fn test_first_registered_wins() {
+        let router = Router::new()
+            .route(Schema::path("/<a>").unwrap(), 1)
+            .route(Schema::path("/<b>").unwrap(), 2);
+        assert_eq!(*router.at("/x").unwrap().handler, 1);
+    }
+
+    #[test]
+    fn test_values() {
+        let router = router();
+        let route_match = router.at("/users/1/posts/2").unwrap();
+        assert_eq!(route_match.values, {
+            let mut map = HashMap::new();
+            map.insert("id".to_owned(), SegmentValue::U64(1));
+            map.insert("page".to_owned(), SegmentValue::U64(2));
+            map
+        });
+    }
+
+    #[test]
+    fn test_parse() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Posts{
+            id: u64,
+            page: Option<u64>,
+        }
+
+        let router = router();
+        let (handler, posts) = router.parse::<Posts>("/users/1/posts/%32").unwrap();
+        assert_eq!(*handler, "posts");
+        assert_eq!(posts.unwrap(), Posts{id: 1, page: Some(2)});
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct File<'a>{
+            owner: &'a str,
+            path: String,
+            tag: Vec<u8>,
+            raw: bool,
+        }
+
+        let router = Router::new().route(Schema::path("/<owner>/files/<path..>?<tag:u8..>&<raw:bool>").unwrap(), "file");
+        let route_match = router.at("/ann%20b/files/a/b?tag=1&tag=2&raw=true").unwrap();
+        let file: File = route_match.parse().unwrap();
+        assert_eq!(file, File{owner: "ann b", path: "a/b".to_owned(), tag: vec![1, 2], raw: true});
+        let values: HashMap<String, serde_json::Value> = route_match.parse().unwrap();
+        assert_eq!(values["path"], serde_json::json!(["a", "b"]));
+        assert_eq!(values["tag"], serde_json::json!([1, 2]));
+        let (_, file) = router.parse::<HashMap<String, String>>("/ann/files/a?raw=false").unwrap();
+        assert!(file.is_err());
+    }
+
+    #[test]
+    fn test_fallback() {
+        // the query parameter rules out the first route, so the second is tried
+        let router = Router::new()
+            .route(Schema::path("/search?<q>").unwrap(), "query")
+            .route(Schema::path("/search").unwrap(), "plain");
+        assert_eq!(*router.at("/search").unwrap().handler, "plain");
+        assert_eq!(*router.at("/search?q=rust").unwrap().handler, "query");
+        assert!(router.at("/search/rust").is_none());
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(rename_all = "snake_case")]
+        enum Search {
+            Query{q: String},
+            Plain,
+        }
+
+        let router = EnumRouter::new()
+            .variant("query", Schema::path("/search?<q>").unwrap())
+            .variant("plain", Schema::path("/search").unwrap());
+        assert_eq!(router.parse::<Search>("/search").unwrap(), Search::Plain);
+        assert_eq!(router.parse::<Search>("/search?q=rust").unwrap(), Search::Query{q: "rust".to_owned()});
+    }
+
+    #[test]
+    fn test_constraints() {
+        let router = Router::new()
+            .route(Schema::path("/posts/<id:u64>").unwrap(), "any_id")
+            .route(Schema::path("/posts/<id:u64(1..=10)>").unwrap(), "small_id")
+            .route(Schema::path("/posts/<title>").unwrap(), "by_title")
+            .route(Schema::path("/posts/<slug:String(^[a-z-]+$)>").unwrap(), "by_slug");
+        assert_eq!(*router.at("/posts/3").unwrap().handler, "small_id");
+        assert_eq!(*router.at("/posts/11").unwrap().handler, "any_id");
+        assert_eq!(*router.at("/posts/hello-world").unwrap().handler, "by_slug");
+        assert_eq!(*router.at("/posts/Hello").unwrap().handler, "by_title");
+    }
+
+    #[test]
+    fn test_patterns() {
+        let router = Router::new()
+            .route(Schema::path("/files/<name>").unwrap(), "file")
+            .route(Schema::path("/files/<name>.<ext>").unwrap(), "file_with_ext")
+            .route(Schema::path("/files/index.html").unwrap(), "index")
+            .route(Schema::path("/v<version:u32>/items").unwrap(), "items");
+        assert_eq!(*router.at("/files/index.html").unwrap().handler, "index");
+        assert_eq!(*router.at("/files/notes.txt").unwrap().handler, "file_with_ext");
+        assert_eq!(*router.at("/files/notes").unwrap().handler, "file");
+        let route_match = router.at("/v2/items").unwrap();
+        assert_eq!(*route_match.handler, "items");
+        assert_eq!(route_match.values.get("version"), Some(&SegmentValue::U32(2)));
+        assert!(router.at("/vx/items").is_none());
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct SearchParams{
+        term: String,
+        page: Option<u32>,
+    }
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    #[serde(rename_all = "snake_case")]
+    enum Route{
+        Home,
+        Me,
+        User{id: u64},
+        UserByName{name: String},
+        Search(SearchParams),
+    }
+
+    fn enum_router() -> EnumRouter {
+        EnumRouter::new()
+            .variant("home", Schema::path("/").unwrap())
+            .variant("user_by_name", Schema::path("/users/<name>").unwrap())
+            .variant("user", Schema::path("/users/<id:u64>").unwrap())
+            .variant("me", Schema::path("/users/me").unwrap())
+            .variant("search", Schema::path("/search/<term>?<page:u32?>").unwrap())
+    }
+
+    #[test]
+    fn test_enum_router() {
+        let router = enum_router();
+        for (path, route) in [
+            ("/", Route::Home),
+            ("/users/me", Route::Me),
+            ("/users/12", Route::User{id: 12}),
+            ("/users/ann", Route::UserByName{name: "ann".to_owned()}),
+            ("/search/rust?page=2", Route::Search(SearchParams{term: "rust".to_owned(), page: Some(2)})),
+        ] {
+            assert_eq!(router.parse::<Route>(path).unwrap(), route);
+            assert_eq!(router.generate(&route).unwrap(), path);
+        }
+        assert_eq!(router.schema("me"), Some(&Schema::path("/users/me").unwrap()));
+        // overlapping schemas are matched by priority, but still reported
+        assert!(!router.router().conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_enum_router_errors() {
+        let router = enum_router();
+        assert_eq!(router.parse::<Route>("/posts/1").unwrap_err().to_string(), "No route matches the path \"/posts/1\"");
+        assert!(matches!(router.parse::<Route>("/search/rust?page=x"), Err(StructPathError::ParseIntError(_))));
+        let router = EnumRouter::new().variant("home", Schema::path("/").unwrap());
+        assert_eq!(router.generate(&Route::Me).unwrap_err().to_string(), "Variant \"me\" has no schema");
+        assert!(matches!(router.generate(&SearchParams{term: "rust".to_owned(), page: None}), Err(StructPathError::NotSupported(_))));
+
+        // a unit variant's schema can capture values, which must still parse
+        let router = EnumRouter::new().variant("home", Schema::path("/home/<id:u8>").unwrap());
+        assert_eq!(router.parse::<Route>("/home/1").unwrap(), Route::Home);
+        assert!(router.parse::<Route>("/home/x").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! serde support for schemas, so route tables can be loaded from config files.
+//!
+//! A `Schema` is serialized as its path schema string, and deserialized from either a path schema
+//! string or its structured form, see `structured`. Either way it is checked the same way
+//! `Schema::path` checks a path schema, and custom types are looked up in the global registry.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::{self, SerializeStruct};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    custom, grammar, Constraint, MatchMode, QueryParamSchema, Schema, SegmentSchema, SegmentType,
+    SegmentValueSchema,
+};
+
+/// A `SegmentType` is written as it is in a path schema, e.g. `u64` or `bool(yes|no)`
+impl Serialize for SegmentType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SegmentType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let text = <Cow<str>>::deserialize(deserializer)?;
+        custom::with_global_registry(|registry| grammar::parse_segment_type(&text, registry)).map_err(de::Error::custom)
+    }
+}
+
+/// A `SegmentValueSchema` is written as a struct, e.g. `{"name": "page", "type": "u32", "constraint": "1..=1000"}`
+impl Serialize for SegmentValueSchema {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        let mut state = serializer.serialize_struct("SegmentValueSchema", 3)?;
+        state.serialize_field("name", &self.name)?;
+        state.serialize_field("type", &self.segment_type)?;
+        match &self.constraint {
+            Some(constraint) => state.serialize_field("constraint", &constraint.to_string())?,
+            None => state.skip_field("constraint")?,
+        }
+        state.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ValueFields {
+    name: String,
+    #[serde(rename = "type", default = "string_type")]
+    segment_type: SegmentType,
+    #[serde(default)]
+    constraint: Option<String>,
+}
+
+fn string_type() -> SegmentType {
+    SegmentType::String
+}
+
+impl<'de> Deserialize<'de> for SegmentValueSchema {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let ValueFields{name, segment_type, constraint} = ValueFields::deserialize(deserializer)?;
+        let constraint = match constraint {
+            Some(text) => {
+                let constraint = custom::with_global_registry(|registry| Constraint::parse(&text, &segment_type, registry));
+                Some(constraint.map_err(de::Error::custom)?)
+            },
+            None => None,
+        };
+        Ok(SegmentValueSchema{name, segment_type, constraint})
+    }
+}
+
+/// A `Schema` is written as its path schema string, see `structured` for the structured form
+impl Serialize for Schema {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.to_path_string().map_err(ser::Error::custom)?)
+    }
+}
+
+/// The structured form of a `Schema`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaFields {
+    #[serde(default)]
+    segments: Vec<SegmentSchema>,
+    #[serde(default)]
+    query: Vec<QueryParamSchema>,
+    #[serde(default)]
+    match_mode: MatchMode,
+}
+
+/// Write the structured form of a `Schema`, leaving out an empty query and the default match mode
+pub(crate) fn serialize_structured<S>(schema: &Schema, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+    let mut state = serializer.serialize_struct("Schema", 3)?;
+    state.serialize_field("segments", &schema.segments)?;
+    if schema.query.is_empty() {
+        state.skip_field("query")?;
+    } else {
+        state.serialize_field("query", &schema.query)?;
+    }
+    if schema.match_mode == MatchMode::Strict {
+        state.skip_field("match_mode")?;
+    } else {
+        state.serialize_field("match_mode", &schema.match_mode)?;
+    }
+    state.end()
+}
+
+impl<'de> Deserialize<'de> for Schema {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        deserializer.deserialize_any(SchemaVisitor)
+    }
+}
+
+struct SchemaVisitor;
+
+impl<'de> Visitor<'de> for SchemaVisitor {
+    type Value = Schema;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a path schema string or a map of segments")
+    }
+
+    fn visit_str<E>(self, path: &str) -> Result<Schema, E> where E: de::Error {
+        Schema::path(path).map_err(E::custom)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Schema, A::Error> where A: MapAccess<'de> {
+        let SchemaFields{segments, query, match_mode} = SchemaFields::deserialize(MapAccessDeserializer::new(map))?;
+        let schema = Schema{segments, query, match_mode, ..Schema::new()};
+        grammar::check_schema(&schema).map_err(de::Error::custom)?;
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn from_json(value: serde_json::Value) -> Result<Schema, String> {
+        serde_json::from_value(value).map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn test_string_form() {
+        let schema = Schema::path("/users/<id:u64>/posts/<page:u32(1..)?>?<sort?>").unwrap();
+        assert_eq!(serde_json::to_value(&schema).unwrap(), json!("/users/<id:u64>/posts/<page:u32(1..)?>?<sort?>"));
+        assert_eq!(from_json(json!("/users/<id:u64>/posts/<page:u32(1..)?>?<sort?>")).unwrap(), schema);
+        assert_eq!(from_json(json!("/users/<id:u9>")).unwrap_err(), "Unrecognized type \"u9\" at byte 7");
+
+        let schema = Schema::new().value_with("code", SegmentType::String, "unregistered", |code| code.len() == 3);
+        assert_eq!(
+            serde_json::to_value(&schema).unwrap_err().to_string(),
+            "Schema can't be written as a path schema: Path schema syntax error in \"<code:String(@unregistered)>\" at byte 1: \
+            Unrecognized predicate \"unregistered\"",
+        );
+        let schema = Schema::new().value("a", SegmentType::String).value("a", SegmentType::U8);
+        assert!(serde_json::to_value(&schema).is_err());
+    }
+
+    #[test]
+    fn test_structured_form() {
+        let schema = from_json(json!({
+            "segments": [
+                {"literal": "files"},
+                {"value": {"name": "id", "type": "u64", "constraint": "1.."}},
+                {"pattern": [{"value": {"name": "name"}}, {"literal": "."}, {"value": {"name": "ext", "type": "png|jpg"}}]},
+            ],
+            "query": [{"repeated": {"name": "tag"}}],
+            "match_mode": "prefix",
+        })).unwrap();
+        assert_eq!(schema, Schema::path("/files/<id:u64(1..)>/<name>.<ext:png|jpg>/<..>?<tag..>").unwrap());
+
+        let mut json = vec![];
+        serialize_structured(&Schema::path("/a/<rest..>").unwrap(), &mut serde_json::Serializer::new(&mut json)).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&json).unwrap(),
+            json!({"segments": [{"literal": "a"}, {"tail": "rest"}]}),
+        );
+    }
+
+    #[test]
+    fn test_structured_form_errors() {
+        let value = |name: &str, segment_type: &str| json!({"value": {"name": name, "type": segment_type}});
+        let error = |segments: serde_json::Value| from_json(json!({"segments": segments})).unwrap_err();
+        assert_eq!(error(json!([value("a", "u8"), value("a", "u16")])), "Value name \"a\" is used more than once");
+        assert_eq!(error(json!([value("a.b", "u8"), value("a", "u8")])), "Value name \"a\" clashes with \"a.b\"");
+        assert_eq!(error(json!([value("1a", "u8")])), "Expected the name \"1a\" to be an identifier");
+        assert_eq!(error(json!([{"tail": "rest"}, {"literal": "a"}])), "Tail segment must be the last segment");
+        assert_eq!(error(json!([{"optional": {"name": "a"}}, {"literal": "b"}])), "Only optional segments may follow an optional segment");
+        assert_eq!(
+            error(json!([{"pattern": [{"value": {"name": "a"}}, {"value": {"name": "b"}}]}])),
+            "Values in a segment must be separated by literal text",
+        );
+        assert!(error(json!([value("a", "u9")])).starts_with("Unrecognized type \"u9\""));
+        assert!(error(json!([{"value": {"name": "a", "type": "u8", "constraint": "x"}}])).starts_with("Expected a range"));
+        assert!(error(json!([{"value": {"name": "a", "colour": "red"}}])).starts_with("unknown field `colour`"));
+    }
+}
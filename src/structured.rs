@@ -0,0 +1,45 @@
+//! serde helpers for writing a `Schema` as a structure listing its segments.
+//!
+//! A `Schema` serializes as its path schema string by default. Use
+//! `#[serde(with = "structpath::structured")]` to write the structured form instead, e.g. for
+//! config files edited by hand. Either form can be read back, and both are checked the same way
+//! `Schema::path` checks a path schema.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use structpath::Schema;
+//!
+//! #[derive(Deserialize, Serialize, PartialEq, Debug)]
+//! struct Route {
+//!     #[serde(with = "structpath::structured")]
+//!     schema: Schema,
+//!     upstream: String,
+//! }
+//!
+//! let route = Route{schema: Schema::path("/users/<id:u64>?<page:u32?>").unwrap(), upstream: "users".to_owned()};
+//! let json = serde_json::to_string(&route).unwrap();
+//! assert_eq!(
+//!     json,
+//!     r#"{"schema":{"segments":[{"literal":"users"},{"value":{"name":"id","type":"u64"}}],"query":[{"optional":{"name":"page","type":"u32"}}]},"upstream":"users"}"#,
+//! );
+//! assert_eq!(serde_json::from_str::<Route>(&json).unwrap(), route);
+//!
+//! let route: Route = serde_json::from_str(r#"{"schema": "/users/<id:u64>?<page:u32?>", "upstream": "users"}"#).unwrap();
+//! assert_eq!(route.schema, Schema::path("/users/<id:u64>?<page:u32?>").unwrap());
+//! ```
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::Schema;
+
+/// Serialize a `Schema` in its structured form
+pub fn serialize<S>(schema: &Schema, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+    crate::schema_serde::serialize_structured(schema, serializer)
+}
+
+/// Deserialize a `Schema` from either its structured form or a path schema string
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Schema, D::Error> where D: Deserializer<'de> {
+    Schema::deserialize(deserializer)
+}
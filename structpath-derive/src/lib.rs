@@ -0,0 +1,626 @@
+//! structpath-derive provides `#[derive(StructPath)]`, which ties a struct to a structpath path
+//! schema and checks at compile time that the two agree.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use structpath_derive::StructPath;
+//!
+//! #[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+//! #[structpath("/foo/<foo_id:u128>/bar/<bar>")]
+//! struct FooParams {
+//!     foo_id: u128,
+//!     bar: String,
+//! }
+//!
+//! let params = FooParams::parse("/foo/1/bar/baz").unwrap();
+//! assert_eq!(params, FooParams{foo_id: 1, bar: "baz".to_owned()});
+//! assert_eq!(params.to_path(), "/foo/1/bar/baz");
+//! ```
+//!
+//! Every value in the schema must have a field of the same name, and fields with primitive types
+//! must match the schema's type, so a typo such as `#[structpath("/foo/<fooid:u128>")]` or a field
+//! declared as `foo_id: u64` is a compile error rather than a runtime one. A nested struct's value,
+//! e.g. `<org.id:u64>`, only needs a field named `org`, and values missing from the struct are
+//! allowed if it has a `#[serde(flatten)]` field; neither is checked until parsing.
+//!
+//! An enum of routes puts a path schema on each variant instead, and is parsed into whichever
+//! variant's schema matches the path, using the same rules as `structpath::Router`:
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use structpath_derive::StructPath;
+//!
+//! #[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+//! enum Route {
+//!     #[structpath("/users/<id:u64>")]
+//!     User { id: u64 },
+//!     #[structpath("/repos/<org>/<name>")]
+//!     Repo { org: String, name: String },
+//! }
+//!
+//! assert_eq!(Route::parse("/users/12").unwrap(), Route::User{id: 12});
+//! assert_eq!(Route::Repo{org: "acme".to_owned(), name: "widgets".to_owned()}.to_path(), "/repos/acme/widgets");
+//! ```
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use structpath::{PatternPart, QueryParamSchema, Schema, SegmentParser, SegmentSchema, SegmentType, SegmentTypeRegistry};
+use syn::parse::ParseStream;
+use syn::punctuated::Punctuated;
+use syn::{
+    parenthesized, parse_macro_input, Attribute, Data, DataEnum, DeriveInput, Expr, ExprLit, Field, Fields,
+    GenericArgument, Ident, Lit, LitStr, Meta, MetaNameValue, PathArguments, Token, Type, Variant,
+};
+
+/// Derive `schema`, `parse` and `to_path` for a struct or an enum of routes from `#[structpath("...")]` attributes
+///
+/// The generated methods are:
+///
+/// - `fn schema() -> &'static structpath::Schema`, the parsed schema
+/// - `fn parse(path: &str) -> Result<Self, structpath::StructPathError>`, which requires the struct
+///   to implement `serde::Deserialize`
+/// - `fn to_path(&self) -> String`, which requires the struct to implement `serde::Serialize`
+///
+/// For an enum every variant needs its own `#[structpath("...")]` attribute, and must be a unit
+/// variant, a newtype variant or have named fields. The generated methods are then:
+///
+/// - `fn router() -> &'static structpath::EnumRouter`, with each variant's schema
+/// - `fn schema(&self) -> &'static structpath::Schema`, the schema for this value's variant
+/// - `fn parse` and `fn to_path` as above, choosing the variant by its schema
+///
+/// Custom segment types must be listed in the attribute, e.g.
+/// `#[structpath("/users/<id:uuid>", custom(uuid))]`, and registered with
+/// `structpath::register_segment_type` before the schema is first used.
+#[proc_macro_derive(StructPath, attributes(structpath))]
+pub fn derive_struct_path(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    match &input.data {
+        Data::Struct(data) => expand_struct(input, &data.fields),
+        Data::Enum(data) => expand_enum(input, data),
+        Data::Union(_) => Err(syn::Error::new(Span::call_site(), "StructPath can only be derived for structs and enums")),
+    }
+}
+
+fn expand_struct(input: &DeriveInput, fields: &Fields) -> syn::Result<proc_macro2::TokenStream> {
+    let (path, custom_types) = schema_attribute(&input.attrs, Span::call_site())?;
+    let schema = parse_schema(&path, &custom_types)?;
+    let fields = match fields {
+        Fields::Named(fields) => &fields.named,
+        _ => return Err(syn::Error::new(Span::call_site(), "StructPath can only be derived for structs with named fields")),
+    };
+    check_fields(&schema, fields, &input.ident.to_string(), &path)?;
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// The `Schema` from this struct's `#[structpath]` attribute
+            pub fn schema() -> &'static ::structpath::Schema {
+                static SCHEMA: ::std::sync::OnceLock<::structpath::Schema> = ::std::sync::OnceLock::new();
+                SCHEMA.get_or_init(|| {
+                    ::structpath::Schema::path(#path)
+                        .expect("path schema is checked by #[derive(StructPath)], custom segment types must be registered before use")
+                })
+            }
+
+            /// Parse a concrete path into this struct
+            pub fn parse<'structpath>(path: &'structpath str) -> ::std::result::Result<Self, ::structpath::StructPathError>
+                where Self: ::serde::Deserialize<'structpath> {
+                Self::schema().parse_str(path)
+            }
+
+            /// Generate the path for this struct
+            ///
+            /// Panics if the path can't be generated, e.g. if an optional value is missing but a
+            /// later one is present.
+            pub fn to_path(&self) -> ::std::string::String where Self: ::serde::Serialize {
+                match Self::schema().generate(self) {
+                    Ok(path) => path,
+                    Err(error) => panic!("failed to generate path for {}: {}", stringify!(#ident), error),
+                }
+            }
+        }
+    })
+}
+
+fn expand_enum(input: &DeriveInput, data: &DataEnum) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(attribute) = input.attrs.iter().find(|attribute| attribute.path().is_ident("structpath")) {
+        return Err(syn::Error::new_spanned(attribute, "put a #[structpath(\"...\")] attribute on each variant of an enum"));
+    }
+    let rename_all = serde_name_value(&input.attrs, "rename_all");
+    let mut names = vec![];
+    let mut paths = vec![];
+    let mut patterns = vec![];
+    for variant in &data.variants {
+        let (path, custom_types) = schema_attribute(&variant.attrs, variant.ident.span())?;
+        let schema = parse_schema(&path, &custom_types)?;
+        let owner = format!("{}::{}", input.ident, variant.ident);
+        let variant_ident = &variant.ident;
+        patterns.push(match &variant.fields {
+            Fields::Named(fields) => {
+                check_fields(&schema, &fields.named, &owner, &path)?;
+                quote!(Self::#variant_ident{..})
+            },
+            // a newtype variant's value is only checked when parsing
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote!(Self::#variant_ident(..)),
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(variant, "StructPath variants must be unit, newtype or have named fields"));
+            },
+            Fields::Unit => {
+                if let Some((name, _)) = schema.fields().first() {
+                    return Err(syn::Error::new(path.span(), format!("unit variant `{}` has no field for the path schema value `{}`", owner, name)));
+                }
+                quote!(Self::#variant_ident)
+            },
+        });
+        names.push(serde_variant_name(variant, rename_all.as_ref())?);
+        paths.push(path);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// The `EnumRouter` built from the `#[structpath]` attribute on each variant
+            pub fn router() -> &'static ::structpath::EnumRouter {
+                static ROUTER: ::std::sync::OnceLock<::structpath::EnumRouter> = ::std::sync::OnceLock::new();
+                ROUTER.get_or_init(|| {
+                    ::structpath::EnumRouter::new()
+                        #(.variant(#names, ::structpath::Schema::path(#paths)
+                            .expect("path schema is checked by #[derive(StructPath)], custom segment types must be registered before use")))*
+                })
+            }
+
+            /// The `Schema` for this value's variant
+            pub fn schema(&self) -> &'static ::structpath::Schema {
+                let variant = match self {
+                    #(#patterns => #names,)*
+                };
+                Self::router().schema(variant).expect("every variant has a schema")
+            }
+
+            /// Parse a concrete path into the variant whose schema matches it
+            pub fn parse<'structpath>(path: &'structpath str) -> ::std::result::Result<Self, ::structpath::StructPathError>
+                where Self: ::serde::Deserialize<'structpath> {
+                Self::router().parse(path)
+            }
+
+            /// Generate the path for this value, using its variant's schema
+            ///
+            /// Panics if the path can't be generated, e.g. if an optional value is missing but a
+            /// later one is present.
+            pub fn to_path(&self) -> ::std::string::String where Self: ::serde::Serialize {
+                match self.schema().generate(self) {
+                    Ok(path) => path,
+                    Err(error) => panic!("failed to generate path for {}: {}", stringify!(#ident), error),
+                }
+            }
+        }
+    })
+}
+
+/// Parse a path schema from an attribute, leaving any custom segment types unchecked
+fn parse_schema(path: &LitStr, custom_types: &[Ident]) -> syn::Result<Schema> {
+    // custom types are only registered at runtime, so any segment using one is left unchecked
+    let registry = custom_types.iter()
+        .fold(SegmentTypeRegistry::new(), |registry, name| registry.register(UncheckedType(name.to_string())));
+    Schema::path_with_registry(path.value(), &registry)
+        .map_err(|error| syn::Error::new(path.span(), format!("invalid path schema: {}", error)))
+}
+
+/// Check every value in a schema has a field of the same name and a matching type, `owner` names
+/// the struct or variant holding the fields in errors
+fn check_fields(schema: &Schema, fields: &Punctuated<Field, Token![,]>, owner: &str, path: &LitStr) -> syn::Result<()> {
+    let has_flattened = fields.iter().any(is_flattened);
+    // the field holding a value, or `None` if it's in a nested or flattened struct, which is only checked when parsing
+    let find_field = |name: &str| {
+        let (field_name, nested) = match name.split_once('.') {
+            Some((field_name, _)) => (field_name, true),
+            None => (name, false),
+        };
+        match fields.iter().find(|field| field.ident.as_ref().is_some_and(|ident| ident == field_name)) {
+            Some(field) if !nested => Ok(Some(field)),
+            Some(_) => Ok(None),
+            None if has_flattened => Ok(None),
+            None => Err(syn::Error::new(
+                path.span(),
+                format!("no field `{}` on `{}` for the path schema value", field_name, owner),
+            )),
+        }
+    };
+    let mut values = vec![];
+    for segment in schema.segments() {
+        match segment {
+            SegmentSchema::Literal(_) => (),
+            SegmentSchema::Value(value) => values.push((value.name(), Some(value.segment_type()), false)),
+            SegmentSchema::Optional(value) => values.push((value.name(), Some(value.segment_type()), true)),
+            SegmentSchema::Tail(name) => values.push((name.as_str(), None, false)),
+            SegmentSchema::Pattern(parts) => for part in parts {
+                if let PatternPart::Value(value) = part {
+                    values.push((value.name(), Some(value.segment_type()), false));
+                }
+            },
+        }
+    }
+    for (name, segment_type, optional) in values {
+        if let Some(field) = find_field(name)? {
+            check_field_type(name, &field.ty, segment_type, optional)?;
+        }
+    }
+    for query_param in schema.query() {
+        let value = query_param.value_schema();
+        let field = match find_field(value.name())? {
+            Some(field) => field,
+            None => continue,
+        };
+        let optional = match query_param {
+            QueryParamSchema::Required(_) => false,
+            QueryParamSchema::Optional(_) => true,
+            // repeated parameters are parsed into sequences, which are only checked when parsing
+            QueryParamSchema::Repeated(_) => continue,
+        };
+        check_field_type(value.name(), &field.ty, Some(value.segment_type()), optional)?;
+    }
+    Ok(())
+}
+
+/// Placeholder for a custom segment type named in `#[structpath(..., custom(...))]`
+#[derive(Debug)]
+struct UncheckedType(String);
+
+impl SegmentParser for UncheckedType {
+    fn name(&self) -> &str {
+        &self.0
+    }
+
+    fn validate(&self, _segment: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The path schema from `#[structpath("...")]`, along with the names of any custom segment types
+/// from `#[structpath("...", custom(uuid, ...))]`
+fn schema_attribute(attrs: &[Attribute], span: Span) -> syn::Result<(LitStr, Vec<Ident>)> {
+    let mut attributes = attrs.iter().filter(|attribute| attribute.path().is_ident("structpath"));
+    let attribute = attributes.next()
+        .ok_or_else(|| syn::Error::new(span, "missing #[structpath(\"...\")] attribute"))?;
+    if let Some(duplicate) = attributes.next() {
+        return Err(syn::Error::new_spanned(duplicate, "only one #[structpath] attribute is allowed"));
+    }
+    attribute.parse_args_with(|input: ParseStream| {
+        let path: LitStr = input.parse()?;
+        let mut custom_types = vec![];
+        if input.parse::<Option<Token![,]>>()?.is_some() {
+            let keyword: Ident = input.parse()?;
+            if keyword != "custom" {
+                return Err(syn::Error::new(keyword.span(), "expected `custom(...)`"));
+            }
+            let content;
+            parenthesized!(content in input);
+            custom_types.extend(Punctuated::<Ident, Token![,]>::parse_terminated(&content)?);
+        }
+        Ok((path, custom_types))
+    })
+}
+
+/// The last identifier of a plain path type, e.g. `String` for `std::string::String`
+fn type_ident(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(type_path) if type_path.qself.is_none() => type_path.path.segments.last().map(|segment| segment.ident.to_string()),
+        Type::Reference(reference) => type_ident(&reference.elem),
+        Type::Group(group) => type_ident(&group.elem),
+        Type::Paren(paren) => type_ident(&paren.elem),
+        _ => None,
+    }
+}
+
+/// The `T` in `Option<T>`, if `ty` is an `Option`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(type_path) if type_path.qself.is_none() => type_path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => match arguments.args.first() {
+            Some(GenericArgument::Type(inner)) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The Rust type names a `SegmentType` can be parsed into, `None` if anything could work
+fn expected_type_names(segment_type: &SegmentType) -> Option<&'static [&'static str]> {
+    Some(match segment_type {
+        SegmentType::F32 => &["f32"],
+        SegmentType::F64 => &["f64"],
+        SegmentType::I8 => &["i8"],
+        SegmentType::I16 => &["i16"],
+        SegmentType::I32 => &["i32"],
+        SegmentType::I64 => &["i64"],
+        SegmentType::I128 => &["i128"],
+        SegmentType::U8 => &["u8"],
+        SegmentType::U16 => &["u16"],
+        SegmentType::U32 => &["u32"],
+        SegmentType::U64 => &["u64"],
+        SegmentType::U128 => &["u128"],
+        SegmentType::String => &["String", "str"],
+        SegmentType::Bool(_) => &["bool"],
+        SegmentType::Char => &["char"],
+        SegmentType::Enum | SegmentType::Choice(_) | SegmentType::Custom(_) => return None,
+    })
+}
+
+const PRIMITIVE_TYPE_NAMES: &[&str] = &[
+    "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+    "u64", "u128", "usize", "String", "str",
+];
+
+/// Every item in `#[serde(...)]` attributes, e.g. `flatten` or `rename = "..."`
+fn serde_metas(attrs: &[Attribute]) -> impl Iterator<Item = Meta> + '_ {
+    attrs.iter()
+        .filter(|attr| attr.path().is_ident("serde"))
+        .filter_map(|attr| attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated).ok())
+        .flatten()
+}
+
+/// The string given to a serde attribute, e.g. `"snake_case"` for `#[serde(rename_all = "snake_case")]`
+fn serde_name_value(attrs: &[Attribute], name: &str) -> Option<LitStr> {
+    serde_metas(attrs).find_map(|meta| match meta {
+        Meta::NameValue(MetaNameValue{path, value: Expr::Lit(ExprLit{lit: Lit::Str(value), ..}), ..}) if path.is_ident(name) => Some(value),
+        _ => None,
+    })
+}
+
+/// Whether a field is marked `#[serde(flatten)]`
+fn is_flattened(field: &Field) -> bool {
+    serde_metas(&field.attrs).any(|meta| meta.path().is_ident("flatten"))
+}
+
+/// The name serde gives a variant, following `#[serde(rename = "...")]` on it or
+/// `#[serde(rename_all = "...")]` on the enum
+fn serde_variant_name(variant: &Variant, rename_all: Option<&LitStr>) -> syn::Result<String> {
+    if let Some(rename) = serde_name_value(&variant.attrs, "rename") {
+        return Ok(rename.value());
+    }
+    let name = variant.ident.to_string();
+    let rename_all = match rename_all {
+        Some(rename_all) => rename_all,
+        None => return Ok(name),
+    };
+    let mut snake_case = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if index > 0 && c.is_uppercase() {
+            snake_case.push('_');
+        }
+        snake_case.extend(c.to_lowercase());
+    }
+    Ok(match rename_all.value().as_str() {
+        "lowercase" => name.to_lowercase(),
+        "UPPERCASE" => name.to_uppercase(),
+        "PascalCase" => name,
+        "camelCase" => name.chars().take(1).flat_map(char::to_lowercase).chain(name.chars().skip(1)).collect(),
+        "snake_case" => snake_case,
+        "SCREAMING_SNAKE_CASE" => snake_case.to_uppercase(),
+        "kebab-case" => snake_case.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => snake_case.to_uppercase().replace('_', "-"),
+        _ => return Err(syn::Error::new_spanned(rename_all, "unrecognized serde rename_all rule")),
+    })
+}
+
+fn check_field_type(name: &str, ty: &Type, segment_type: Option<&SegmentType>, optional: bool) -> syn::Result<()> {
+    let ty = match (option_inner(ty), optional) {
+        (Some(inner), true) => inner,
+        (None, true) => return Err(syn::Error::new_spanned(ty, format!("field `{}` must be an `Option` for an optional segment", name))),
+        (Some(_), false) => return Err(syn::Error::new_spanned(ty, format!("field `{}` is an `Option` but its segment is not optional", name))),
+        (None, false) => ty,
+    };
+    let ident = match type_ident(ty) {
+        Some(ident) if PRIMITIVE_TYPE_NAMES.contains(&ident.as_str()) => ident,
+        // newtypes and other non primitive types are only checked when parsing
+        _ => return Ok(()),
+    };
+    let expected = match segment_type {
+        Some(segment_type) => match expected_type_names(segment_type) {
+            Some(expected) => expected,
+            None => return Ok(()),
+        },
+        // tails can only be parsed into strings or sequences of strings
+        None => &["String", "str"],
+    };
+    if expected.contains(&ident.as_str()) {
+        Ok(())
+    } else {
+        Err(syn::Error::new_spanned(ty, format!("field `{}` has type `{}` but the path schema expects `{}`", name, ident, expected[0])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_error(input: &str) -> String {
+        let input: DeriveInput = syn::parse_str(input).unwrap();
+        expand(&input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_missing_field() {
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<fooid:u128>")] struct Foo { foo_id: u128 }"#),
+            "no field `fooid` on `Foo` for the path schema value",
+            );
+    }
+
+    #[test]
+    fn test_mismatched_type() {
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<foo_id:u128>")] struct Foo { foo_id: u64 }"#),
+            "field `foo_id` has type `u64` but the path schema expects `u128`",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<foo_id>")] struct Foo { foo_id: u64 }"#),
+            "field `foo_id` has type `u64` but the path schema expects `String`",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<path..>")] struct Foo { path: u64 }"#),
+            "field `path` has type `u64` but the path schema expects `String`",
+            );
+    }
+
+    #[test]
+    fn test_optional() {
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<page:u32?>")] struct Foo { page: u32 }"#),
+            "field `page` must be an `Option` for an optional segment",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<page:u32>")] struct Foo { page: Option<u32> }"#),
+            "field `page` is an `Option` but its segment is not optional",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<page:u32?>")] struct Foo { page: Option<u64> }"#),
+            "field `page` has type `u64` but the path schema expects `u32`",
+            );
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(
+            expand_error(r#"#[structpath("/search?<page:u32>&<tag..>")] struct Foo { page: u32 }"#),
+            "no field `tag` on `Foo` for the path schema value",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/search?<page:u32?>")] struct Foo { page: u32 }"#),
+            "field `page` must be an `Option` for an optional segment",
+            );
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert_eq!(
+            expand_error(r#"#[structpath("/img/<w:u32>x<h:u32>.png")] struct Foo { w: u32 }"#),
+            "no field `h` on `Foo` for the path schema value",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/v<version:u32>")] struct Foo { version: u64 }"#),
+            "field `version` has type `u64` but the path schema expects `u32`",
+            );
+    }
+
+    #[test]
+    fn test_nested_and_flattened() {
+        assert_eq!(
+            expand_error(r#"#[structpath("/<org.id:u64>/<repo>")] struct Foo { repo: String }"#),
+            "no field `org` on `Foo` for the path schema value",
+            );
+        let input: DeriveInput = syn::parse_str(r#"#[structpath("/<org.id:u64>/<repo>?<page:u32>")] struct Foo { org: Org, #[serde(default, flatten)] rest: Rest }"#).unwrap();
+        assert!(expand(&input).is_ok());
+        assert_eq!(
+            expand_error(r#"#[structpath("/<repo>/<page:u32>")] struct Foo { #[serde(flatten)] org: Org, page: u64 }"#),
+            "field `page` has type `u64` but the path schema expects `u32`",
+            );
+    }
+
+    #[test]
+    fn test_invalid_attribute() {
+        assert_eq!(
+            expand_error(r#"struct Foo { foo_id: u64 }"#),
+            "missing #[structpath(\"...\")] attribute",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo/<foo_id:u1000>")] struct Foo { foo_id: u64 }"#),
+            "invalid path schema: Unrecognized type \"u1000\" at byte 5",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/foo")] union Foo { foo: u64 }"#),
+            "StructPath can only be derived for structs and enums",
+            );
+    }
+
+    #[test]
+    fn test_enum() {
+        let input: DeriveInput = syn::parse_str(r#"enum Route {
+            #[structpath("/")] Home,
+            #[structpath("/users/<id:u64>")] User { id: u64 },
+            #[structpath("/search/<term>")] Search(SearchParams),
+        }"#).unwrap();
+        assert!(expand(&input).is_ok());
+        assert_eq!(
+            expand_error(r#"enum Route { #[structpath("/")] Home, User { id: u64 } }"#),
+            "missing #[structpath(\"...\")] attribute",
+            );
+        assert_eq!(
+            expand_error(r#"enum Route { #[structpath("/users/<id:u64>")] User { id: u32 } }"#),
+            "field `id` has type `u32` but the path schema expects `u64`",
+            );
+        assert_eq!(
+            expand_error(r#"enum Route { #[structpath("/users/<id:u64>")] User { user_id: u64 } }"#),
+            "no field `id` on `Route::User` for the path schema value",
+            );
+        assert_eq!(
+            expand_error(r#"enum Route { #[structpath("/users/<id:u64>")] User }"#),
+            "unit variant `Route::User` has no field for the path schema value `id`",
+            );
+        assert_eq!(
+            expand_error(r#"enum Route { #[structpath("/users/<id:u64>/<name>")] User(u64, String) }"#),
+            "StructPath variants must be unit, newtype or have named fields",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/")] enum Route { #[structpath("/")] Home }"#),
+            "put a #[structpath(\"...\")] attribute on each variant of an enum",
+            );
+    }
+
+    #[test]
+    fn test_serde_variant_name() {
+        let variant: Variant = syn::parse_str("UserByName").unwrap();
+        let name = |rename_all: &str| serde_variant_name(&variant, Some(&LitStr::new(rename_all, Span::call_site()))).unwrap();
+        assert_eq!(serde_variant_name(&variant, None).unwrap(), "UserByName");
+        assert_eq!(name("lowercase"), "userbyname");
+        assert_eq!(name("camelCase"), "userByName");
+        assert_eq!(name("snake_case"), "user_by_name");
+        assert_eq!(name("SCREAMING-KEBAB-CASE"), "USER-BY-NAME");
+        let variant: Variant = syn::parse_str(r#"#[serde(rename = "by-name")] UserByName"#).unwrap();
+        assert_eq!(serde_variant_name(&variant, None).unwrap(), "by-name");
+    }
+
+    #[test]
+    fn test_custom_types() {
+        assert_eq!(
+            expand_error(r#"#[structpath("/users/<id:uuid>")] struct Foo { id: Uuid }"#),
+            "invalid path schema: Unrecognized type \"uuid\" at byte 7",
+            );
+        assert_eq!(
+            expand_error(r#"#[structpath("/users/<id:uuid>", types(uuid))] struct Foo { id: Uuid }"#),
+            "expected `custom(...)`",
+            );
+        let input: DeriveInput = syn::parse_str(
+            r#"#[structpath("/users/<id:uuid>/<hash:hex>", custom(uuid, hex))] struct Foo { id: Uuid, hash: String }"#,
+        ).unwrap();
+        assert!(expand(&input).is_ok());
+    }
+
+    #[test]
+    fn test_unchecked_types() {
+        let input: DeriveInput = syn::parse_str(
+            r#"#[structpath("/foo/<foo_id:u64>/<path..>")] struct Foo { foo_id: FooId, path: Vec<String> }"#,
+        ).unwrap();
+        assert!(expand(&input).is_ok());
+    }
+}
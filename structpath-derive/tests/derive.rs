@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use structpath::{SegmentType, Schema};
+use structpath_derive::StructPath;
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[structpath("/foo/<foo_id:u128>/bar/<bar>")]
+struct FooParams {
+    foo_id: u128,
+    bar: String,
+}
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[structpath("/items/<category>/<page:u64?>")]
+struct ItemParams {
+    category: String,
+    page: Option<u64>,
+}
+
+#[test]
+fn test_schema() {
+    assert_eq!(
+        *FooParams::schema(),
+        Schema::new()
+            .literal("foo")
+            .value("foo_id", SegmentType::U128)
+            .literal("bar")
+            .value("bar", SegmentType::String),
+        );
+}
+
+#[test]
+fn test_roundtrip() {
+    let params = FooParams::parse("/foo/1/bar/baz").unwrap();
+    assert_eq!(params, FooParams{foo_id: 1, bar: "baz".to_owned()});
+    assert_eq!(params.to_path(), "/foo/1/bar/baz");
+    assert!(FooParams::parse("/foo/x/bar/baz").is_err());
+}
+
+#[test]
+fn test_optional() {
+    let params = ItemParams::parse("/items/books").unwrap();
+    assert_eq!(params, ItemParams{category: "books".to_owned(), page: None});
+    assert_eq!(params.to_path(), "/items/books");
+    let params = ItemParams{category: "books".to_owned(), page: Some(2)};
+    assert_eq!(ItemParams::parse(&params.to_path()).unwrap(), params);
+}
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[structpath("/search/<term>?<page:u32?>&<tag..>")]
+struct SearchParams {
+    term: String,
+    page: Option<u32>,
+    tag: Vec<String>,
+}
+
+#[test]
+fn test_query() {
+    let params = SearchParams::parse("/search/rust?tag=web&page=2&tag=cli").unwrap();
+    assert_eq!(params, SearchParams{term: "rust".to_owned(), page: Some(2), tag: vec!["web".to_owned(), "cli".to_owned()]});
+    assert_eq!(params.to_path(), "/search/rust?page=2&tag=web&tag=cli");
+}
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[structpath("/hosts/<ip:ipv4>", custom(ipv4))]
+struct HostParams {
+    #[serde(with = "structpath::display_fromstr")]
+    ip: std::net::Ipv4Addr,
+}
+
+#[test]
+fn test_custom() {
+    structpath::register_segment_type(structpath::FromStrParser::<std::net::Ipv4Addr>::new("ipv4"));
+    let params = HostParams::parse("/hosts/127.0.0.1").unwrap();
+    assert_eq!(params, HostParams{ip: std::net::Ipv4Addr::LOCALHOST});
+    assert_eq!(params.to_path(), "/hosts/127.0.0.1");
+    assert!(HostParams::parse("/hosts/localhost").is_err());
+}
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[structpath("/v<version:u32>/files/<name>.<ext>")]
+struct FileParams {
+    version: u32,
+    name: String,
+    ext: String,
+}
+
+#[test]
+fn test_pattern() {
+    let params = FileParams::parse("/v2/files/archive.tar.gz").unwrap();
+    assert_eq!(params, FileParams{version: 2, name: "archive.tar".to_owned(), ext: "gz".to_owned()});
+    assert_eq!(params.to_path(), "/v2/files/archive.tar.gz");
+    assert!(FileParams::parse("/2/files/archive.gz").is_err());
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+struct OrgRef {
+    id: u64,
+    name: String,
+}
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[structpath("/orgs/<org.id:u64>/<org.name>/repos/<repo>")]
+struct RepoParams {
+    org: OrgRef,
+    repo: String,
+}
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[structpath("/orgs/<org.id:u64>/<org.name>/repos/<repo>/issues/<number:u32>")]
+struct IssueParams {
+    #[serde(flatten)]
+    repo: RepoParams,
+    number: u32,
+}
+
+#[test]
+fn test_nested_and_flattened() {
+    let params = RepoParams::parse("/orgs/7/acme/repos/widgets").unwrap();
+    assert_eq!(params, RepoParams{org: OrgRef{id: 7, name: "acme".to_owned()}, repo: "widgets".to_owned()});
+    assert_eq!(params.to_path(), "/orgs/7/acme/repos/widgets");
+    let params = IssueParams{repo: params, number: 12};
+    assert_eq!(params.to_path(), "/orgs/7/acme/repos/widgets/issues/12");
+    assert_eq!(IssueParams::parse("/orgs/7/acme/repos/widgets/issues/12").unwrap(), params);
+    assert!(IssueParams::parse("/orgs/x/acme/repos/widgets/issues/12").is_err());
+}
+
+#[derive(Deserialize, Serialize, StructPath, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum Route {
+    #[structpath("/")]
+    Home,
+    #[structpath("/users/me")]
+    Me,
+    #[structpath("/users/<id:u64>")]
+    User { id: u64 },
+    #[structpath("/orgs/<org.id:u64>/<org.name>/repos/<repo>")]
+    RepoPage { org: OrgRef, repo: String },
+    #[structpath("/search/<term>?<page:u32?>&<tag..>")]
+    Search(SearchParams),
+}
+
+#[test]
+fn test_enum() {
+    for (path, route) in [
+        ("/", Route::Home),
+        ("/users/me", Route::Me),
+        ("/users/12", Route::User{id: 12}),
+        ("/orgs/7/acme/repos/widgets", Route::RepoPage{org: OrgRef{id: 7, name: "acme".to_owned()}, repo: "widgets".to_owned()}),
+        ("/search/rust?tag=cli", Route::Search(SearchParams{term: "rust".to_owned(), page: None, tag: vec!["cli".to_owned()]})),
+    ] {
+        assert_eq!(Route::parse(path).unwrap(), route);
+        assert_eq!(route.to_path(), path);
+    }
+    assert_eq!(*Route::User{id: 1}.schema(), Schema::path("/users/<id:u64>").unwrap());
+    assert!(Route::router().schema("repo-page").is_some());
+    assert!(Route::parse("/users/12/posts").is_err());
+}